@@ -0,0 +1,20 @@
+#![no_main]
+
+// cargo +nightly fuzz run process_record
+
+use libactionkv::{ActionKV, ChecksumAlgorithm};
+use libfuzzer_sys::fuzz_target;
+
+// Any byte string must either parse as a sequence of records or stop with an
+// error; it must never panic or try to allocate what the header claims.
+// The first byte picks the checksum algorithm.
+fuzz_target!(|data: &[u8]| {
+    let (algorithm, mut f) = match data.split_first() {
+        Some((first, rest)) => {
+            let all = ChecksumAlgorithm::ALL;
+            (all[*first as usize % all.len()], rest)
+        }
+        None => return,
+    };
+    while ActionKV::process_record(&mut f, algorithm).is_ok() {}
+});
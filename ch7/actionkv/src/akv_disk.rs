@@ -1,3 +1,4 @@
+use libactionkv::cli::Cli;
 use libactionkv::{ActionKV, ByteStr, ByteString};
 use std::collections::HashMap;
 
@@ -8,6 +9,15 @@ Usage:
     akv_mem.exe FILE delete KEY
     akv_mem.exe FILE insert KEY VALUE
     akv_mem.exe FILE update KEY VALUE
+    akv_mem.exe FILE stats [--json]
+    akv_mem.exe FILE backup DEST [--incremental]
+    akv_mem.exe FILE restore BACKUP
+    akv_mem.exe FILE query POINTER VALUE
+    akv_mem.exe FILE grep KEY_REGEX [VALUE_TEXT]
+    akv_mem.exe FILE grep --value VALUE_TEXT
+    akv_mem.exe FILE migrate
+    akv_mem.exe FILE init [crc32|crc32c|xxh3]
+    akv_mem.exe FILE check
 ";
 
 #[cfg(not(target_os = "windows"))]
@@ -17,6 +27,15 @@ Usage:
     akv_mem FILE delete KEY
     akv_mem FILE insert KEY VALUE
     akv_mem FILE update KEY VALUE
+    akv_mem FILE stats [--json]
+    akv_mem FILE backup DEST [--incremental]
+    akv_mem FILE restore BACKUP
+    akv_mem FILE query POINTER VALUE
+    akv_mem FILE grep KEY_REGEX [VALUE_TEXT]
+    akv_mem FILE grep --value VALUE_TEXT
+    akv_mem FILE migrate
+    akv_mem FILE init [crc32|crc32c|xxh3]
+    akv_mem FILE check
 ";
 
 fn store_index_on_disk(a: &mut ActionKV, index_key: &ByteStr) {
@@ -30,18 +49,17 @@ fn main() {
     const INDEX_KEY: &ByteStr = b"+index";
 
     let args: Vec<String> = std::env::args().collect();
-    let fname = args.get(1).expect(&USAGE);
-    let action = args.get(2).expect(&USAGE).as_ref();
-    let key = args.get(3).expect(&USAGE).as_ref();
+    let mut store = match Cli::new(USAGE).internal_keys(&[INDEX_KEY]).run(&args) {
+        Some(store) => store,
+        None => return,
+    };
+    let action = args[2].as_str();
+    let key = args.get(3).expect(USAGE).as_ref();
     let maybe_value = args.get(4);
 
-    let path = std::path::Path::new(&fname);
-    let mut store = ActionKV::open(path).expect("unable to open file");
-    store.load().expect("unable to load data");
-
     match action {
         "get" => {
-            let index_as_bytes = store.get(&INDEX_KEY).unwrap().unwrap();
+            let index_as_bytes = store.get(INDEX_KEY).unwrap().unwrap();
             let index_decoded = bincode::deserialize(&index_as_bytes);
             let index: HashMap<ByteString, u64> = index_decoded.unwrap();
             match index.get(key) {
@@ -54,16 +72,15 @@ fn main() {
         }
         "delete" => store.delete(key).unwrap(),
         "insert" => {
-            let value = maybe_value.expect(&USAGE).as_ref();
+            let value = maybe_value.expect(USAGE).as_ref();
             store.insert(key, value).unwrap();
             store_index_on_disk(&mut store, INDEX_KEY);
         }
         "update" => {
-            let value = maybe_value.expect(&USAGE).as_ref();
+            let value = maybe_value.expect(USAGE).as_ref();
             store.update(key, value).unwrap();
             store_index_on_disk(&mut store, INDEX_KEY);
         }
-        _ => eprintln!("{}", &USAGE),
+        _ => eprintln!("{}", USAGE),
     }
 }
-
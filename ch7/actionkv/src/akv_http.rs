@@ -0,0 +1,388 @@
+use libactionkv::{ActionKV, ByteStr, ByteString};
+use std::io::{self, prelude::*, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::time::Duration;
+
+#[cfg(target_os = "windows")]
+const USAGE: &str = "
+Usage:
+    akv_http.exe FILE [ADDR]
+
+Serves FILE over HTTP on ADDR (default 127.0.0.1:4000):
+    GET    /kv/KEY
+    PUT    /kv/KEY
+    DELETE /kv/KEY
+    GET    /kv?prefix=PREFIX
+    GET    /stats
+";
+
+#[cfg(not(target_os = "windows"))]
+const USAGE: &str = "
+Usage:
+    akv_http FILE [ADDR]
+
+Serves FILE over HTTP on ADDR (default 127.0.0.1:4000):
+    GET    /kv/KEY
+    PUT    /kv/KEY
+    DELETE /kv/KEY
+    GET    /kv?prefix=PREFIX
+    GET    /stats
+";
+
+const DEFAULT_ADDR: &str = "127.0.0.1:4000";
+const MAX_HEADER_LINES: usize = 100;
+// Longest request or header line, line ending included.
+const MAX_LINE_LEN: usize = 8 * 1024;
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+// Connections are served one at a time, so one that stalls must not hold up
+// the rest for long.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+    body: ByteString,
+}
+
+struct Response {
+    status: u16,
+    reason: &'static str,
+    content_type: &'static str,
+    etag: Option<u64>,
+    body: ByteString,
+}
+
+impl Response {
+    fn new(status: u16, reason: &'static str) -> Response {
+        Response {
+            status,
+            reason,
+            content_type: "text/plain",
+            etag: None,
+            body: ByteString::new(),
+        }
+    }
+
+    fn with_body(mut self, content_type: &'static str, body: ByteString) -> Response {
+        self.content_type = content_type;
+        self.body = body;
+        self
+    }
+
+    fn with_etag(mut self, etag: Option<u64>) -> Response {
+        self.etag = etag;
+        self
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let fname = args.get(1).expect(USAGE);
+    let addr = args.get(2).map(String::as_str).unwrap_or(DEFAULT_ADDR);
+
+    let path = std::path::Path::new(&fname);
+    let mut store = ActionKV::open(path).expect("unable to open file");
+    store
+        .enable_bloom_filter(BLOOM_FALSE_POSITIVE_RATE)
+        .expect("unable to read Bloom filter");
+    store.load().expect("unable to load data");
+
+    let listener = TcpListener::bind(addr).expect("unable to bind address");
+    // Printed so that callers binding to port 0 can find out the real port.
+    println!("listening on {}", listener.local_addr().unwrap());
+    io::stdout().flush().unwrap();
+
+    // One connection at a time: `ActionKV` needs `&mut` for every operation.
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("connection failed: {}", err);
+                continue;
+            }
+        };
+        if let Err(err) = handle(&mut store, stream) {
+            eprintln!("request failed: {}", err);
+        }
+    }
+}
+
+fn handle(store: &mut ActionKV, stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader)? {
+        Err(refused) => refused,
+        Ok(req) => match route(store, req) {
+            Ok(response) => response,
+            Err(err) => Response::new(500, "Internal Server Error")
+                .with_body("text/plain", err.to_string().into_bytes()),
+        },
+    };
+    write_response(stream, response)
+}
+
+fn route(store: &mut ActionKV, req: Request) -> io::Result<Response> {
+    // Pick up anything other writers have appended since the last request.
+    store.refresh()?;
+    let key = req.path.strip_prefix("/kv/").map(percent_decode);
+    match (req.method.as_str(), req.path.as_str(), key) {
+        ("GET", "/stats", _) => {
+            let stats = store.stats()?;
+            let body = serde_json::to_vec(&stats)?;
+            Ok(Response::new(200, "OK").with_body("application/json", body))
+        }
+        ("GET", "/kv", _) => list(store, req.query.as_deref()),
+        ("GET", _, Some(key)) => get(store, &key),
+        ("PUT", _, Some(key)) | ("DELETE", _, Some(key)) if ActionKV::is_reserved_key(&key) => {
+            Ok(Response::new(400, "Bad Request")
+                .with_body("text/plain", b"key is reserved".to_vec()))
+        }
+        ("PUT", _, Some(key)) => put(store, &key, &req),
+        ("DELETE", _, Some(key)) => delete(store, &key, &req),
+        (_, "/stats", _) | (_, "/kv", _) | (_, _, Some(_)) => {
+            Ok(Response::new(405, "Method Not Allowed"))
+        }
+        _ => Ok(Response::new(404, "Not Found")),
+    }
+}
+
+// Deleted keys hold an empty value, so an empty value reads as not found.
+fn current(store: &mut ActionKV, key: &ByteStr) -> io::Result<Option<(u64, ByteString)>> {
+    match store.find(key)? {
+        Some((_, value)) if value.is_empty() => Ok(None),
+        found => Ok(found),
+    }
+}
+
+fn get(store: &mut ActionKV, key: &ByteStr) -> io::Result<Response> {
+    match current(store, key)? {
+        None => Ok(Response::new(404, "Not Found")),
+        Some((pos, value)) => Ok(Response::new(200, "OK")
+            .with_body("application/octet-stream", value)
+            .with_etag(Some(pos))),
+    }
+}
+
+fn list(store: &mut ActionKV, query: Option<&str>) -> io::Result<Response> {
+    let prefix = query
+        .unwrap_or("")
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("prefix="))
+        .map(percent_decode)
+        .unwrap_or_default();
+    let candidates: Vec<ByteString> = store
+        .index
+        .keys()
+        .filter(|k| k.starts_with(&prefix))
+        .cloned()
+        .collect();
+    let mut keys = Vec::new();
+    for key in candidates {
+        if current(store, &key)?.is_some() {
+            // Keys are percent-encoded so binary keys survive the trip
+            // through JSON and can be pasted back into `/kv/KEY`.
+            keys.push(percent_encode(&key));
+        }
+    }
+    keys.sort();
+    let body = serde_json::to_vec(&keys)?;
+    Ok(Response::new(200, "OK").with_body("application/json", body))
+}
+
+fn put(store: &mut ActionKV, key: &ByteStr, req: &Request) -> io::Result<Response> {
+    let existing = current(store, key)?.map(|(pos, _)| pos);
+    if let Some(failed) = check_preconditions(req, existing) {
+        return Ok(failed);
+    }
+    if req.body.is_empty() {
+        return Ok(Response::new(400, "Bad Request")
+            .with_body("text/plain", b"empty values are reserved for deletes".to_vec()));
+    }
+    if !write(store, key, &req.body)? {
+        return Ok(Response::new(412, "Precondition Failed"));
+    }
+    let etag = store.index.get(key).copied();
+    let response = match existing {
+        None => Response::new(201, "Created"),
+        Some(_) => Response::new(200, "OK"),
+    };
+    Ok(response.with_etag(etag))
+}
+
+fn delete(store: &mut ActionKV, key: &ByteStr, req: &Request) -> io::Result<Response> {
+    let existing = current(store, key)?.map(|(pos, _)| pos);
+    if let Some(failed) = check_preconditions(req, existing) {
+        return Ok(failed);
+    }
+    if existing.is_none() {
+        return Ok(Response::new(404, "Not Found"));
+    }
+    if !write(store, key, b"")? {
+        return Ok(Response::new(412, "Precondition Failed"));
+    }
+    Ok(Response::new(204, "No Content"))
+}
+
+// Goes through a transaction so that the write is refused if another writer
+// touched `key` after the preconditions were checked.
+fn write(store: &mut ActionKV, key: &ByteStr, value: &ByteStr) -> io::Result<bool> {
+    let mut txn = store.transaction();
+    txn.get(store, key)?;
+    txn.insert(key, value);
+    store.commit(txn)
+}
+
+// ETags are the offset of the key's latest record, which changes on every
+// write to it.
+fn check_preconditions(req: &Request, existing: Option<u64>) -> Option<Response> {
+    let failed = || Some(Response::new(412, "Precondition Failed"));
+    if let Some(tags) = &req.if_match {
+        match existing {
+            None => return failed(),
+            Some(pos) if !etag_matches(tags, pos) => return failed(),
+            Some(_) => {}
+        }
+    }
+    if let Some(tags) = &req.if_none_match {
+        if let Some(pos) = existing {
+            if etag_matches(tags, pos) {
+                return failed();
+            }
+        }
+    }
+    None
+}
+
+fn etag_matches(header: &str, pos: u64) -> bool {
+    let etag = format!("\"{}\"", pos);
+    header
+        .split(',')
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag == etag)
+}
+
+// Reads a request, or gives back the response to refuse it with.
+fn read_request<R: BufRead>(reader: &mut R) -> io::Result<Result<Request, Response>> {
+    let bad_request = || Ok(Err(Response::new(400, "Bad Request")));
+    let too_large = || Ok(Err(Response::new(431, "Request Header Fields Too Large")));
+    let mut line = String::new();
+    if !read_line(reader, &mut line)? {
+        return bad_request();
+    }
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return bad_request(),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_string(), Some(query.to_string())),
+        None => (target, None),
+    };
+
+    let mut content_length = 0;
+    let mut if_match = None;
+    let mut if_none_match = None;
+    let mut headers = 0;
+    loop {
+        line.clear();
+        if !read_line(reader, &mut line)? {
+            return too_large();
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        headers += 1;
+        if headers > MAX_HEADER_LINES {
+            return too_large();
+        }
+        let (name, value) = match header.split_once(':') {
+            Some((name, value)) => (name.trim().to_ascii_lowercase(), value.trim()),
+            None => return bad_request(),
+        };
+        match name.as_str() {
+            "content-length" => match value.parse() {
+                Ok(len) => content_length = len,
+                Err(_) => return bad_request(),
+            },
+            "if-match" => if_match = Some(value.to_string()),
+            "if-none-match" => if_none_match = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    if content_length > MAX_BODY_LEN {
+        return Ok(Err(Response::new(413, "Payload Too Large")));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Ok(Request {
+        method,
+        path,
+        query,
+        if_match,
+        if_none_match,
+        body,
+    }))
+}
+
+// Reads a line into `line`, returning `false` if it is longer than
+// `MAX_LINE_LEN`, in which case the rest of it is left unread.
+fn read_line<R: BufRead>(reader: &mut R, line: &mut String) -> io::Result<bool> {
+    let n = reader.by_ref().take(MAX_LINE_LEN as u64).read_line(line)?;
+    Ok(n < MAX_LINE_LEN || line.ends_with('\n'))
+}
+
+fn write_response(mut stream: TcpStream, response: Response) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.reason,
+        response.content_type,
+        response.body.len()
+    );
+    if let Some(pos) = response.etag {
+        head.push_str(&format!("ETag: \"{}\"\r\n", pos));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+fn percent_decode(s: &str) -> ByteString {
+    let bytes = s.as_bytes();
+    let mut out = ByteString::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok());
+        match (bytes[i], hex.and_then(|h| u8::from_str_radix(h, 16).ok())) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+fn percent_encode(bytes: &ByteStr) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
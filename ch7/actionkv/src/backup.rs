@@ -0,0 +1,247 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*, BufReader, BufWriter, SeekFrom};
+use std::path::Path;
+
+use crate::{
+    with_suffix, ActionKV, ChecksumAlgorithm, FileHeader, FileStorage, StorageReader, HEADER_LEN,
+};
+
+impl ActionKV {
+    /// Copies the log, header included, to `dest` up to the end of the last
+    /// complete record, and returns the offset the backup covers. Every
+    /// record is checked against its CRC on the way out and again in the
+    /// copy. The copy is written to a temporary file first, so `dest` is
+    /// never left half done.
+    pub fn backup_to(&mut self, dest: &Path) -> io::Result<u64> {
+        let len = self.storage.len()?;
+        let reader = StorageReader::new(&mut *self.storage, HEADER_LEN);
+        let checksum = self.header.checksum;
+        let end = verify_records(reader, checksum, HEADER_LEN, len)?.end;
+        let src = StorageReader::new(&mut *self.storage, 0);
+        replace_with_copy(src, end, &with_suffix(dest, ".tmp"), dest)?;
+        Ok(end)
+    }
+
+    /// Brings an earlier backup made by `backup_to` up to date by appending
+    /// only what has been written since. `dest` must be a backup of this
+    /// store, which is checked by comparing file headers, and must end on a
+    /// record that is the same in the log, or nothing is copied and an
+    /// `InvalidData` error is returned. A missing `dest` gets a full backup.
+    /// If the copy fails part way, `dest` is cut back to where it ended, so
+    /// the next run can pick up from there.
+    pub fn backup_incremental(&mut self, dest: &Path) -> io::Result<u64> {
+        let mut out = match OpenOptions::new().read(true).write(true).open(dest) {
+            Ok(out) => out,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return self.backup_to(dest);
+            }
+            Err(err) => return Err(err),
+        };
+        if check_header(&mut out)? != self.header {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "file is not a backup of this store",
+            ));
+        }
+        let start = out.metadata()?.len();
+        let len = self.storage.len()?;
+        if start > len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "backup is longer than the store it would be updated from",
+            ));
+        }
+        let checksum = self.header.checksum;
+        let verified = verify_records(&mut out, checksum, HEADER_LEN, start)?;
+        if verified.end != start {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "backup ends with an incomplete record",
+            ));
+        }
+        let last = verified.last_batch.unwrap_or(start);
+        if !self.same_range(&mut out, last, start)? {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "backup has diverged from the store",
+            ));
+        }
+        let reader = StorageReader::new(&mut *self.storage, start);
+        let end = verify_records(reader, checksum, start, len)?.end;
+        let src = StorageReader::new(&mut *self.storage, start);
+        let copied = copy_range(src, start, end, &mut out)
+            .and_then(|()| check_copy(&mut out, checksum, start, end));
+        if let Err(err) = copied {
+            // The original error is the one worth reporting.
+            let _ = out.set_len(start).and_then(|()| out.sync_all());
+            return Err(err);
+        }
+        Ok(end)
+    }
+
+    /// Replaces the store's log with the backup at `src` and reloads the
+    /// index. The backup is validated in full before anything is touched.
+    /// For a file-backed log it is then copied and validated once more next
+    /// to the log and swapped in with a rename, so the current log is left
+    /// alone if anything fails.
+    ///
+    /// Other storage is truncated and refilled, which is not atomic: if that
+    /// fails part way, the log is left empty or with only part of the
+    /// backup, and `restore` has to be run again.
+    pub fn restore(&mut self, src: &Path) -> io::Result<()> {
+        let mut backup = File::open(src)?;
+        let len = backup.metadata()?.len();
+        let header = check_header(&mut backup)?;
+        let end = verify_records(&mut backup, header.checksum, HEADER_LEN, len)?.end;
+        if end != len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "backup ends with an incomplete record",
+            ));
+        }
+        backup.seek(SeekFrom::Start(0))?;
+        match self.path.clone() {
+            Some(path) => {
+                replace_with_copy(&mut backup, end, &with_suffix(&path, ".restore"), &path)?;
+                self.storage = Box::new(FileStorage::open(&path)?);
+            }
+            None => {
+                let mut data = Vec::with_capacity(end as usize);
+                backup.read_to_end(&mut data)?;
+                self.storage.truncate(0)?;
+                self.storage.append(&data)?;
+                self.storage.sync()?;
+            }
+        }
+
+        self.header = header;
+        self.end = HEADER_LEN;
+        self.index.clear();
+        for s in self.secondary.values_mut() {
+            s.clear();
+        }
+        self.load()
+    }
+
+    // Whether the bytes from `start` to `end` are the same in `copy` as in
+    // the log.
+    fn same_range(&mut self, copy: &mut File, start: u64, end: u64) -> io::Result<bool> {
+        let len = (end - start) as usize;
+        let mut ours = Vec::with_capacity(len);
+        StorageReader::new(&mut *self.storage, start)
+            .take(len as u64)
+            .read_to_end(&mut ours)?;
+        let mut theirs = Vec::with_capacity(len);
+        copy.seek(SeekFrom::Start(start))?;
+        Read::by_ref(copy).take(len as u64).read_to_end(&mut theirs)?;
+        Ok(ours == theirs)
+    }
+}
+
+/// How far `verify_records` got.
+pub(crate) struct Verified {
+    /// Offset just past the last complete record or transaction.
+    pub end: u64,
+    /// Where the last complete record or transaction starts.
+    pub last_batch: Option<u64>,
+    /// Key/value records read, not counting transaction markers.
+    pub records: u64,
+}
+
+// Checks the records from `start` (where `f` is positioned) up to `end`. A
+// partial record or transaction at the end is not an error: it is either a
+// crash or a writer that is still appending. Any other error names the
+// offset of the batch it was found in.
+pub(crate) fn verify_records<R: Read>(
+    f: R,
+    checksum: ChecksumAlgorithm,
+    start: u64,
+    end: u64,
+) -> io::Result<Verified> {
+    let mut f = BufReader::new(f.take(end - start));
+    let mut pos = start;
+    let mut last_batch = None;
+    let mut records = 0;
+    loop {
+        let batch_start = pos;
+        match ActionKV::process_batch(&mut f, checksum, &mut pos) {
+            Ok(batch) => {
+                last_batch = Some(batch_start);
+                records += batch.len() as u64;
+            }
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(err) => {
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("record at offset {}: {}", pos, err),
+                ))
+            }
+        }
+    }
+    Ok(Verified { end: pos, last_batch, records })
+}
+
+// Copies `src` up to `end` to `tmp`, checks the copy and then renames it over
+// `dest`. `tmp` is removed again if anything fails.
+fn replace_with_copy<R: Read>(src: R, end: u64, tmp: &Path, dest: &Path) -> io::Result<()> {
+    let copied = create_rw(tmp).and_then(|mut out| {
+        copy_range(src, 0, end, &mut out)?;
+        let header = check_header(&mut out)?;
+        check_copy(&mut out, header.checksum, HEADER_LEN, end)
+    });
+    if let Err(err) = copied.and_then(|()| fs::rename(tmp, dest)) {
+        let _ = fs::remove_file(tmp);
+        return Err(err);
+    }
+    Ok(())
+}
+
+fn create_rw(path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+// Copies from `src` (positioned at `start`) to the same offsets in `dest`.
+fn copy_range<R: Read>(src: R, start: u64, end: u64, dest: &mut File) -> io::Result<()> {
+    dest.seek(SeekFrom::Start(start))?;
+    let mut dest_buf = BufWriter::new(&mut *dest);
+    let copied = io::copy(&mut src.take(end - start), &mut dest_buf)?;
+    if copied != end - start {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    dest_buf.flush()?;
+    drop(dest_buf);
+    dest.set_len(end)?;
+    dest.sync_all()
+}
+
+// Reads and validates the header of `f`, leaving it positioned just after.
+fn check_header(f: &mut File) -> io::Result<FileHeader> {
+    f.seek(SeekFrom::Start(0))?;
+    let mut buf = Vec::with_capacity(HEADER_LEN as usize);
+    Read::by_ref(f).take(HEADER_LEN).read_to_end(&mut buf)?;
+    FileHeader::from_bytes(&buf)
+}
+
+// Re-reads what was just written, so a bad disk is noticed at backup time
+// rather than when the backup is needed.
+fn check_copy(
+    copy: &mut File,
+    checksum: ChecksumAlgorithm,
+    start: u64,
+    end: u64,
+) -> io::Result<()> {
+    copy.seek(SeekFrom::Start(start))?;
+    let checked = verify_records(&mut *copy, checksum, start, end)?.end;
+    if checked != end {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "backup copy failed verification",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,146 @@
+use std::f64::consts::LN_2;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ByteStr;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+// Any odd constant works, it only has to differ from `FNV_OFFSET`.
+const SECOND_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+/// Counters for lookups that went through a Bloom filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct BloomStats {
+    pub lookups: u64,
+    /// Lookups the filter answered "definitely absent" on its own, without
+    /// going to the index.
+    pub avoided: u64,
+    /// Lookups the filter let through for keys that turned out to be absent.
+    pub false_positives: u64,
+}
+
+/// What the Bloom filter looks like and how it has done so far, as part of
+/// `StoreStats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BloomReport {
+    pub num_bits: u64,
+    pub num_hashes: u32,
+    /// The rate the filter was sized for.
+    pub target_false_positive_rate: f64,
+    /// The rate to expect given how many bits are set now.
+    pub estimated_false_positive_rate: f64,
+    /// Lookups made through this handle.
+    pub lookups: BloomStats,
+}
+
+// Identifies the log a saved filter was built from: its length, the `id`
+// from its header, and where its last record starts along with that
+// record's checksum. Length alone would match a restored backup or a
+// migrated log that happens to be the same size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct LogStamp {
+    pub len: u64,
+    pub id: u32,
+    pub last_record: Option<(u64, u32)>,
+}
+
+impl BloomFilter {
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> BloomFilter {
+        assert!(
+            false_positive_rate > 0.0 && false_positive_rate < 1.0,
+            "false positive rate must be between 0 and 1 (got {})",
+            false_positive_rate
+        );
+        let n = expected_items.max(1) as f64;
+        let m = (-n * false_positive_rate.ln() / (LN_2 * LN_2)).ceil().max(64.0);
+        let k = ((m / n) * LN_2).round().max(1.0);
+        let num_bits = m as u64;
+        let words = num_bits.div_ceil(64) as usize;
+        BloomFilter {
+            bits: vec![0; words],
+            num_bits,
+            num_hashes: k as u32,
+        }
+    }
+
+    pub fn insert(&mut self, key: &ByteStr) {
+        let (h1, h2) = hashes(key);
+        for i in 0..self.num_hashes {
+            let bit = self.bit_index(h1, h2, i);
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    pub fn may_contain(&self, key: &ByteStr) -> bool {
+        let (h1, h2) = hashes(key);
+        (0..self.num_hashes).all(|i| {
+            let bit = self.bit_index(h1, h2, i);
+            self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0
+        })
+    }
+
+    pub fn num_bits(&self) -> u64 {
+        self.num_bits
+    }
+
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// The false positive rate to expect from how full the filter is, which
+    /// goes up as keys are added past what it was sized for.
+    pub fn estimated_false_positive_rate(&self) -> f64 {
+        let set: u64 = self.bits.iter().map(|w| w.count_ones() as u64).sum();
+        (set as f64 / self.num_bits as f64).powi(self.num_hashes as i32)
+    }
+
+    #[inline]
+    fn bit_index(&self, h1: u64, h2: u64, i: u32) -> u64 {
+        h1.wrapping_add((i as u64).wrapping_mul(h2)) % self.num_bits
+    }
+
+    // Reads a filter saved by `save`, returning it with the stamp of the log
+    // it was built from. A missing file gives `None`, and so does one that
+    // can't be decoded, as the filter is only a cache and `load` on the
+    // store builds a new one.
+    pub(crate) fn load(path: &Path) -> io::Result<Option<(LogStamp, BloomFilter)>> {
+        let f = match File::open(path) {
+            Ok(f) => f,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        Ok(bincode::deserialize_from(BufReader::new(f)).ok())
+    }
+
+    // Writes the filter along with the stamp of the log it covers, so that
+    // a stale file is ignored instead of trusted.
+    pub(crate) fn save(&self, path: &Path, stamp: &LogStamp) -> io::Result<()> {
+        let f = BufWriter::new(File::create(path)?);
+        bincode::serialize_into(f, &(stamp, self)).map_err(io::Error::other)
+    }
+}
+
+// Two independent 64-bit FNV-1a hashes, combined with the Kirsch-Mitzenmacher
+// trick to derive `num_hashes` bit positions. FNV is used rather than
+// `DefaultHasher` because the filter is persisted and must hash the same way
+// across Rust releases.
+fn hashes(key: &ByteStr) -> (u64, u64) {
+    let mut h1 = FNV_OFFSET;
+    let mut h2 = SECOND_SEED;
+    for byte in key {
+        h1 = (h1 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+        h2 = (h2 ^ *byte as u64).wrapping_mul(FNV_PRIME);
+    }
+    (h1, h2 | 1)
+}
@@ -0,0 +1,178 @@
+use std::path::Path;
+
+use crate::{ActionKV, ByteStr, ByteString, ChecksumAlgorithm, ScanFilter, FORMAT_VERSION};
+
+const BLOOM_FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// The subcommands that `akv_mem` and `akv_disk` have in common, which is
+/// all of them apart from get, delete, insert and update.
+pub struct Cli<'a> {
+    usage: &'a str,
+    internal_keys: &'a [&'a ByteStr],
+}
+
+impl<'a> Cli<'a> {
+    pub fn new(usage: &'a str) -> Cli<'a> {
+        Cli {
+            usage,
+            internal_keys: &[],
+        }
+    }
+
+    /// Keys the binary keeps its own bookkeeping under, which `stats` and
+    /// `grep` leave out.
+    pub fn internal_keys(mut self, keys: &'a [&'a ByteStr]) -> Cli<'a> {
+        self.internal_keys = keys;
+        self
+    }
+
+    /// Runs the subcommand in `args`, which are the program's arguments.
+    /// When it isn't one of the shared ones, returns the store, opened and
+    /// loaded, for the binary to carry on with.
+    pub fn run(&self, args: &[String]) -> Option<ActionKV> {
+        let fname = args.get(1).expect(self.usage);
+        let action = args.get(2).expect(self.usage).as_str();
+        let maybe_key = args.get(3);
+        let maybe_value = args.get(4);
+
+        let path = Path::new(fname);
+        match action {
+            "migrate" => {
+                match ActionKV::migrate(path).expect("unable to migrate file") {
+                    true => println!("{} upgraded to format version {}", fname, FORMAT_VERSION),
+                    false => println!("{} is already up to date", fname),
+                }
+                return None;
+            }
+            "init" => {
+                self.init(path, maybe_key);
+                return None;
+            }
+            _ => {}
+        }
+
+        let mut store = ActionKV::open(path).expect("unable to open file");
+        if action == "check" {
+            self.check(&mut store, fname);
+            return None;
+        }
+
+        store
+            .enable_bloom_filter(BLOOM_FALSE_POSITIVE_RATE)
+            .expect("unable to read Bloom filter");
+        store.load().expect("unable to load data");
+
+        match action {
+            "stats" => self.stats(&mut store, maybe_key),
+            "backup" => self.backup(&mut store, maybe_key, maybe_value),
+            "restore" => {
+                let src = Path::new(maybe_key.expect(self.usage));
+                store.restore(src).expect("restore failed");
+            }
+            "query" => self.query(&mut store, maybe_key, maybe_value),
+            "grep" => self.grep(&mut store, maybe_key, maybe_value),
+            _ => return Some(store),
+        }
+        None
+    }
+
+    fn init(&self, path: &Path, maybe_checksum: Option<&String>) {
+        if path.exists() {
+            eprintln!("{} already exists", path.display());
+            std::process::exit(1);
+        }
+        let checksum = match maybe_checksum {
+            None => ChecksumAlgorithm::default(),
+            Some(name) => name.parse().expect(self.usage),
+        };
+        ActionKV::open_with_checksum(path, checksum).expect("unable to create file");
+        println!("created {} using {} checksums", path.display(), checksum);
+    }
+
+    fn check(&self, store: &mut ActionKV, fname: &str) {
+        match store.check() {
+            Ok(report) => {
+                println!("{} records ok ({} checksums)", report.records, report.checksum);
+                if report.trailing > 0 {
+                    println!(
+                        "{} bytes of incomplete write after offset {}",
+                        report.trailing, report.end
+                    );
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: {}", fname, err);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    fn stats(&self, store: &mut ActionKV, flag: Option<&String>) {
+        let stats = store
+            .stats_excluding(self.internal_keys)
+            .expect("unable to read statistics");
+        match flag.map(String::as_str) {
+            None => print!("{}", stats),
+            Some("--json") => println!("{}", serde_json::to_string_pretty(&stats).unwrap()),
+            Some(_) => eprintln!("{}", self.usage),
+        }
+    }
+
+    fn backup(&self, store: &mut ActionKV, dest: Option<&String>, flag: Option<&String>) {
+        let dest = Path::new(dest.expect(self.usage));
+        let offset = match flag.map(String::as_str) {
+            None => store.backup_to(dest),
+            Some("--incremental") => store.backup_incremental(dest),
+            Some(_) => {
+                eprintln!("{}", self.usage);
+                return;
+            }
+        };
+        println!("backed up to offset {}", offset.expect("backup failed"));
+    }
+
+    fn query(&self, store: &mut ActionKV, pointer: Option<&String>, raw: Option<&String>) {
+        let pointer = pointer.expect(self.usage);
+        let raw = raw.expect(self.usage);
+        // VALUE is read as JSON if it parses, so `42` finds numbers and
+        // `"42"` or plain `abc` find strings.
+        let value = serde_json::from_str(raw)
+            .unwrap_or_else(|_| serde_json::Value::String(raw.to_string()));
+        // The index is named after its pointer and kept in the log, so only
+        // the first query for a pointer has to build it.
+        if store.secondary_index(pointer).is_none() {
+            store.create_index(pointer, pointer).expect("unable to build index");
+        }
+        for key in store.find_by(pointer, &value).unwrap() {
+            println!("{:?}", key);
+        }
+    }
+
+    // `grep --value TEXT` searches values alone.
+    fn grep(&self, store: &mut ActionKV, key_regex: Option<&String>, text: Option<&String>) {
+        let internal: Vec<ByteString> = self.internal_keys.iter().map(|k| k.to_vec()).collect();
+        let mut filter = ScanFilter::new().key(move |key| !internal.iter().any(|k| k == key));
+        let key_regex = key_regex.expect(self.usage);
+        if key_regex == "--value" {
+            if text.is_none() {
+                eprintln!("{}", self.usage);
+                return;
+            }
+        } else {
+            let re = regex::bytes::Regex::new(key_regex).expect("invalid KEY_REGEX");
+            filter = filter.key_regex(re);
+        }
+        if let Some(text) = text {
+            filter = filter.value_contains(text.as_ref());
+        }
+        for record in store.scan(&filter) {
+            let (pos, kv) = record.expect("unable to read record");
+            println!(
+                "{}\t{}\t{}",
+                pos,
+                String::from_utf8_lossy(&kv.key),
+                String::from_utf8_lossy(&kv.value)
+            );
+        }
+    }
+}
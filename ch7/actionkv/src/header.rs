@@ -0,0 +1,185 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, prelude::*, BufWriter, SeekFrom};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::backup::verify_records;
+use crate::{with_suffix, ActionKV, ChecksumAlgorithm, Storage, StorageReader, CRC32};
+
+pub const MAGIC: &[u8; 8] = b"ACTIONKV";
+pub const FORMAT_VERSION: u16 = 1;
+/// Size of the header on disk. Records start straight after it.
+pub const HEADER_LEN: u64 = 32;
+
+/// Values are compressed. Reserved; no version of actionkv writes it yet.
+pub const FLAG_COMPRESSED: u16 = 1 << 0;
+/// Values are encrypted. Reserved; no version of actionkv writes it yet.
+pub const FLAG_ENCRYPTED: u16 = 1 << 1;
+// Flags this version knows how to read.
+const SUPPORTED_FLAGS: u16 = 0;
+
+/// The first `HEADER_LEN` bytes of every actionkv file:
+///
+/// ```text
+/// magic     [u8; 8]  "ACTIONKV"
+/// version   u16
+/// flags     u16
+/// checksum  u8       record checksum algorithm
+/// (unused)  [u8; 3]
+/// created   u64      seconds since the Unix epoch
+/// id        u32      random, tells apart stores created in the same second
+/// crc       u32      CRC of the 28 bytes before it
+/// ```
+///
+/// All integers are little-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u16,
+    pub flags: u16,
+    pub checksum: ChecksumAlgorithm,
+    pub created: u64,
+    pub id: u32,
+}
+
+impl FileHeader {
+    pub fn new(checksum: ChecksumAlgorithm) -> FileHeader {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        // Not cryptographically random, just unlikely to repeat.
+        let id = CRC32.checksum(&[
+            &now.subsec_nanos().to_le_bytes()[..],
+            &std::process::id().to_le_bytes()[..],
+        ].concat());
+        FileHeader {
+            version: FORMAT_VERSION,
+            flags: 0,
+            checksum,
+            created: now.as_secs(),
+            id,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(HEADER_LEN as usize);
+        buf.extend_from_slice(MAGIC);
+        // Writing to a `Vec` can't fail.
+        buf.write_u16::<LittleEndian>(self.version).unwrap();
+        buf.write_u16::<LittleEndian>(self.flags).unwrap();
+        buf.push(self.checksum.id());
+        buf.extend_from_slice(&[0; 3]);
+        buf.write_u64::<LittleEndian>(self.created).unwrap();
+        buf.write_u32::<LittleEndian>(self.id).unwrap();
+        let crc = CRC32.checksum(&buf);
+        buf.write_u32::<LittleEndian>(crc).unwrap();
+        buf
+    }
+
+    /// Parses and validates a header, rejecting anything this version can't
+    /// read.
+    pub fn from_bytes(buf: &[u8]) -> io::Result<FileHeader> {
+        if buf.len() < HEADER_LEN as usize || &buf[..8] != MAGIC {
+            return Err(invalid(
+                "not an actionkv file (files written before headers were \
+                 introduced need to be upgraded with `migrate`)",
+            ));
+        }
+        let (fields, mut crc) = buf[..HEADER_LEN as usize].split_at(28);
+        if CRC32.checksum(fields) != crc.read_u32::<LittleEndian>()? {
+            return Err(invalid("file header is corrupt"));
+        }
+        let mut f = &fields[8..];
+        let version = f.read_u16::<LittleEndian>()?;
+        let flags = f.read_u16::<LittleEndian>()?;
+        let checksum_id = f.read_u8()?;
+        f.read_exact(&mut [0; 3])?;
+        let created = f.read_u64::<LittleEndian>()?;
+        let id = f.read_u32::<LittleEndian>()?;
+
+        if version > FORMAT_VERSION {
+            return Err(invalid(&format!(
+                "file format version {} is newer than this actionkv supports ({})",
+                version, FORMAT_VERSION
+            )));
+        }
+        if flags & !SUPPORTED_FLAGS != 0 {
+            return Err(invalid(&format!(
+                "file uses features this actionkv doesn't support (flags {:#06x})",
+                flags
+            )));
+        }
+        let checksum = ChecksumAlgorithm::from_id(checksum_id)
+            .ok_or_else(|| invalid(&format!("unknown checksum algorithm {}", checksum_id)))?;
+        Ok(FileHeader {
+            version,
+            flags,
+            checksum,
+            created,
+            id,
+        })
+    }
+
+    /// Reads the header of `storage`, writing a fresh one for a store using
+    /// `checksum` first if the storage is empty.
+    pub(crate) fn read_or_init(
+        storage: &mut dyn Storage,
+        checksum: ChecksumAlgorithm,
+    ) -> io::Result<FileHeader> {
+        if storage.is_empty()? {
+            let header = FileHeader::new(checksum);
+            storage.append(&header.to_bytes())?;
+            return Ok(header);
+        }
+        let mut buf = Vec::with_capacity(HEADER_LEN as usize);
+        StorageReader::new(storage, 0)
+            .take(HEADER_LEN)
+            .read_to_end(&mut buf)?;
+        FileHeader::from_bytes(&buf)
+    }
+}
+
+impl ActionKV {
+    /// Upgrades a log written before files had a header, by writing a copy
+    /// with a header in front and renaming it over the original. Returns
+    /// `false` if the file already has a header. An incomplete record at the
+    /// end of the old file is dropped; any other damage aborts the upgrade
+    /// and leaves the file as it was. Old files always used
+    /// `ChecksumAlgorithm::Crc32Cksum`, so the upgraded file does too.
+    pub fn migrate(path: &Path) -> io::Result<bool> {
+        let mut old = File::open(path)?;
+        let len = old.metadata()?.len();
+        let mut magic = Vec::with_capacity(MAGIC.len());
+        Read::by_ref(&mut old).take(MAGIC.len() as u64).read_to_end(&mut magic)?;
+        if magic == MAGIC {
+            return Ok(false);
+        }
+
+        old.seek(SeekFrom::Start(0))?;
+        let checksum = ChecksumAlgorithm::Crc32Cksum;
+        let end = verify_records(&mut old, checksum, 0, len)?.end;
+        old.seek(SeekFrom::Start(0))?;
+
+        let tmp = with_suffix(path, ".migrate");
+        {
+            let mut out = BufWriter::new(
+                OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&tmp)?,
+            );
+            out.write_all(&FileHeader::new(checksum).to_bytes())?;
+            io::copy(&mut old.take(end), &mut out)?;
+            out.flush()?;
+            out.get_ref().sync_all()?;
+        }
+        fs::rename(&tmp, path)?;
+        Ok(true)
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
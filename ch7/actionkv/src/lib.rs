@@ -1,16 +1,50 @@
 use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::{self, prelude::*, BufReader, BufWriter, SeekFrom};
-use std::path::Path;
+use std::ffi::OsString;
+use std::io::{self, prelude::*, BufReader};
+use std::path::{Path, PathBuf};
 
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use crc::{Crc, CRC_32_CKSUM};
 use serde::{Deserialize, Serialize};
 
+mod backup;
+mod bloom;
+mod checksum;
+pub mod cli;
+mod header;
+mod scan;
+mod secondary;
+mod stats;
+mod storage;
+mod txn;
+
+pub use bloom::{BloomFilter, BloomReport, BloomStats};
+pub use checksum::{CheckReport, ChecksumAlgorithm};
+pub use header::{
+    FileHeader, FLAG_COMPRESSED, FLAG_ENCRYPTED, FORMAT_VERSION, HEADER_LEN, MAGIC,
+};
+pub use scan::{Scan, ScanFilter};
+pub use secondary::SecondaryIndex;
+pub use stats::{StoreStats, ValueSize};
+pub use storage::{Fault, FaultyStorage, FileStorage, MemStorage, Storage, StorageReader};
+pub use txn::Transaction;
+use bloom::LogStamp;
+use stats::StatsCollector;
+
 pub type ByteString = Vec<u8>;
 pub type ByteStr = [u8];
 
 const CRC32: Crc<u32> = Crc::<u32>::new(&CRC_32_CKSUM);
+const LARGEST_VALUES_REPORTED: usize = 5;
+// checksum, key length and value length, each a u32
+const RECORD_HEADER_LEN: u64 = 12;
+const MAX_PREALLOC: u64 = 64 * 1024;
+// Key of the record that starts a transaction. Its value is the number of
+// records that follow, as a little-endian u32.
+const TXN_MARKER: &ByteStr = b"+txn";
+// Key of the record that holds the secondary index definitions, a JSON
+// object from index name to pointer. The latest one wins.
+const INDEXES_KEY: &ByteStr = b"+indexes";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct KeyValuePair {
@@ -20,40 +54,257 @@ pub struct KeyValuePair {
 
 #[derive(Debug)]
 pub struct ActionKV {
-    f: File,
+    storage: Box<dyn Storage>,
+    // Only set for logs opened from a file. Used to place the Bloom filter
+    // next to the log and to swap in a restored backup.
+    path: Option<PathBuf>,
+    header: FileHeader,
     pub index: HashMap<ByteString, u64>,
+    bloom: Option<Bloom>,
+    secondary: HashMap<String, SecondaryIndex>,
+    // Offset just past the last record this handle has seen.
+    end: u64,
+    // Set when the log is known to end in a torn record past `end`, to the
+    // length of the log when it was found. The record is cut off before
+    // anything else is appended.
+    torn_tail: Option<u64>,
+}
+
+#[derive(Debug)]
+struct Bloom {
+    false_positive_rate: f64,
+    filter: Option<BloomFilter>,
+    // The log `filter` was last saved for, if it has been.
+    saved: Option<LogStamp>,
+    stats: BloomStats,
 }
 
 impl ActionKV {
+    /// Opens the log at `path`, creating it if needed. Fails if the file
+    /// doesn't start with a valid header that this version can read.
     pub fn open(path: &Path) -> io::Result<Self> {
-        let f = OpenOptions::new()
-            .read(true)
-            .write(true) // implied by append
-            .create(true)
-            .append(true)
-            .open(path)?;
-        let index = HashMap::new();
-        Ok(ActionKV { f, index })
+        ActionKV::open_with_checksum(path, ChecksumAlgorithm::default())
+    }
+
+    /// Like `open`, but a newly created log uses `checksum` for its
+    /// records. An existing log keeps the algorithm its header declares.
+    pub fn open_with_checksum(path: &Path, checksum: ChecksumAlgorithm) -> io::Result<Self> {
+        let storage = FileStorage::open(path)?;
+        let mut store = ActionKV::with_storage_and_checksum(Box::new(storage), checksum)?;
+        store.path = Some(path.to_path_buf());
+        Ok(store)
+    }
+
+    /// Uses `storage` for the log instead of a file. Call `load` before use,
+    /// as with `open`.
+    pub fn with_storage(storage: Box<dyn Storage>) -> io::Result<Self> {
+        ActionKV::with_storage_and_checksum(storage, ChecksumAlgorithm::default())
+    }
+
+    pub fn with_storage_and_checksum(
+        mut storage: Box<dyn Storage>,
+        checksum: ChecksumAlgorithm,
+    ) -> io::Result<Self> {
+        let header = FileHeader::read_or_init(&mut *storage, checksum)?;
+        Ok(ActionKV {
+            storage,
+            path: None,
+            header,
+            index: HashMap::new(),
+            bloom: None,
+            secondary: HashMap::new(),
+            end: HEADER_LEN,
+            torn_tail: None,
+        })
+    }
+
+    pub fn header(&self) -> &FileHeader {
+        &self.header
+    }
+
+    /// Keeps a Bloom filter over the keys in the log, which answers lookups
+    /// of absent keys before the index is consulted. The index is held in
+    /// memory, so this saves a hash map lookup rather than a read of the
+    /// log; the filter is groundwork for an index that lives on disk.
+    ///
+    /// For file-backed logs the filter is saved next to the log as
+    /// `FILE.bloom`. A saved filter is picked up straight away if it was
+    /// built from this very log: same header `id`, same length and the same
+    /// last record. Otherwise `load` builds a new one and saves that. A `false_positive_rate` outside (0, 1) is refused with
+    /// an `InvalidInput` error.
+    pub fn enable_bloom_filter(&mut self, false_positive_rate: f64) -> io::Result<()> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "false positive rate must be between 0 and 1 (got {})",
+                    false_positive_rate
+                ),
+            ));
+        }
+        let saved = match self.bloom_path() {
+            Some(path) => BloomFilter::load(&path)?,
+            None => None,
+        };
+        let (saved, filter) = match saved {
+            Some((stamp, filter)) if self.stamp_matches(&stamp)? => (Some(stamp), Some(filter)),
+            _ => (None, None),
+        };
+        self.bloom = Some(Bloom {
+            false_positive_rate,
+            filter,
+            saved,
+            stats: BloomStats::default(),
+        });
+        Ok(())
+    }
+
+    pub fn bloom_stats(&self) -> Option<BloomStats> {
+        self.bloom.as_ref().map(|b| b.stats)
+    }
+
+    /// The Bloom filter in use, if one is enabled and has been built or
+    /// picked up from disk.
+    pub fn bloom_filter(&self) -> Option<&BloomFilter> {
+        self.bloom.as_ref().and_then(|b| b.filter.as_ref())
+    }
+
+    fn bloom_report(&self) -> Option<BloomReport> {
+        let bloom = self.bloom.as_ref()?;
+        let filter = bloom.filter.as_ref()?;
+        Some(BloomReport {
+            num_bits: filter.num_bits(),
+            num_hashes: filter.num_hashes(),
+            target_false_positive_rate: bloom.false_positive_rate,
+            estimated_false_positive_rate: filter.estimated_false_positive_rate(),
+            lookups: bloom.stats,
+        })
+    }
+
+    fn bloom_path(&self) -> Option<PathBuf> {
+        self.path.as_ref().map(|p| with_suffix(p, ".bloom"))
+    }
+
+    // `last_record` is where the last complete record in the log starts.
+    fn log_stamp(&mut self, last_record: Option<u64>) -> io::Result<LogStamp> {
+        let last_record = match last_record {
+            None => None,
+            Some(pos) => {
+                let mut f = StorageReader::new(&mut *self.storage, pos);
+                Some((pos, f.read_u32::<LittleEndian>()?))
+            }
+        };
+        Ok(LogStamp {
+            len: self.storage.len()?,
+            id: self.header.id,
+            last_record,
+        })
     }
 
-    fn process_record<R: Read>(f: &mut R) -> io::Result<KeyValuePair> {
+    fn stamp_matches(&mut self, stamp: &LogStamp) -> io::Result<bool> {
+        if stamp.len != self.storage.len()? || stamp.id != self.header.id {
+            return Ok(false);
+        }
+        let (pos, saved_checksum) = match stamp.last_record {
+            None => return Ok(stamp.len == HEADER_LEN),
+            Some(last_record) => last_record,
+        };
+        let mut head = [0; RECORD_HEADER_LEN as usize];
+        let mut f = StorageReader::new(&mut *self.storage, pos);
+        match f.read_exact(&mut head) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err),
+        }
+        let mut head = &head[..];
+        let checksum = head.read_u32::<LittleEndian>()?;
+        let key_len = head.read_u32::<LittleEndian>()? as u64;
+        let val_len = head.read_u32::<LittleEndian>()? as u64;
+        let end = pos + RECORD_HEADER_LEN + key_len + val_len;
+        Ok(checksum == saved_checksum && end == stamp.len)
+    }
+
+    // Builds the filter from the index and saves it, unless the one in use
+    // was saved for the log as it is now.
+    fn rebuild_bloom_filter(&mut self, last_record: Option<u64>) -> io::Result<()> {
+        let false_positive_rate = match &self.bloom {
+            None => return Ok(()),
+            Some(bloom) => bloom.false_positive_rate,
+        };
+        let stamp = self.log_stamp(last_record)?;
+        if let Some(Bloom { filter: Some(_), saved: Some(saved), .. }) = &self.bloom {
+            if *saved == stamp {
+                return Ok(());
+            }
+        }
+        let mut filter = BloomFilter::new(self.index.len(), false_positive_rate);
+        for key in self.index.keys() {
+            filter.insert(key);
+        }
+        let mut saved = None;
+        if let Some(path) = self.bloom_path() {
+            filter.save(&path, &stamp)?;
+            saved = Some(stamp);
+        }
+        if let Some(bloom) = self.bloom.as_mut() {
+            bloom.filter = Some(filter);
+            bloom.saved = saved;
+        }
+        Ok(())
+    }
+
+    // `false` means the key is definitely not in the log.
+    fn bloom_check(&mut self, key: &ByteStr) -> bool {
+        let (filter, stats) = match self.bloom.as_mut() {
+            Some(Bloom { filter: Some(filter), stats, .. }) => (filter, stats),
+            _ => return true,
+        };
+        stats.lookups += 1;
+        if filter.may_contain(key) {
+            true
+        } else {
+            stats.avoided += 1;
+            false
+        }
+    }
+
+    fn bloom_miss(&mut self) {
+        if let Some(bloom) = self.bloom.as_mut() {
+            bloom.stats.false_positives += 1;
+        }
+    }
+
+    /// Reads a single record from `f`, checked with `checksum`. A record cut
+    /// short returns an `UnexpectedEof` error and one that fails its
+    /// checksum `InvalidData`.
+    pub fn process_record<R: Read>(
+        f: &mut R,
+        checksum: ChecksumAlgorithm,
+    ) -> io::Result<KeyValuePair> {
         let saved_checksum = f.read_u32::<LittleEndian>()?;
         let key_len = f.read_u32::<LittleEndian>()?;
         let val_len = f.read_u32::<LittleEndian>()?;
-        let data_len = key_len + val_len;
-        let mut data = ByteString::with_capacity(data_len as usize);
+        let data_len = key_len as u64 + val_len as u64;
+        // The lengths are untrusted until the checksum has been checked, so
+        // let the buffer grow as data arrives rather than reserving up to
+        // 8 GiB on the word of a corrupt header.
+        let mut data = ByteString::with_capacity(data_len.min(MAX_PREALLOC) as usize);
         {
             f.by_ref() // Required because `take` creates a new Read instance.
-                .take(data_len as u64)
+                .take(data_len)
                 .read_to_end(&mut data)?;
         }
-        debug_assert_eq!(data.len(), data_len as usize);
-        let mut digest = CRC32.digest();
-        digest.update(&data);
-        let checksum = digest.finalize();
-        if checksum != saved_checksum {
-            panic!("data corruption encountered ({:08x} != {:08x})",
-                   checksum, saved_checksum);
+        if data.len() != data_len as usize {
+            // A record cut short by a crash mid-append.
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let computed = checksum.checksum(&data);
+        if computed != saved_checksum {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("data corruption encountered ({:08x} != {:08x})",
+                        computed, saved_checksum),
+            ));
         }
         let value = data.split_off(key_len as usize);
         let key = data;
@@ -61,16 +312,74 @@ impl ActionKV {
     }
 
     pub fn seek_to_end(&mut self) -> io::Result<u64> {
-        self.f.seek(SeekFrom::End(0))
+        self.storage.len()
     }
 
     pub fn load(&mut self) -> io::Result<()> {
-        let mut f = BufReader::new(&mut self.f);
+        let index = &mut self.index;
+        let secondary = &mut self.secondary;
+        let checksum = self.header.checksum;
+        let mut last_record = None;
+        let mut definitions = None;
+        self.end = ActionKV::for_each_record(&mut *self.storage, checksum, HEADER_LEN, |pos, kv| {
+            last_record = Some(pos);
+            if kv.key == INDEXES_KEY {
+                definitions = Some(kv.value);
+                return;
+            }
+            for s in secondary.values_mut() {
+                s.update(&kv.key, &kv.value);
+            }
+            index.insert(kv.key, pos);
+        })?;
+        let len = self.storage.len()?;
+        self.torn_tail = if len > self.end { Some(len) } else { None };
+        if let Some(definitions) = definitions {
+            self.apply_index_definitions(&definitions)?;
+        }
+        self.rebuild_bloom_filter(last_record)
+    }
+
+    /// Picks up records appended to the log by other writers since it was
+    /// last loaded or refreshed. This handle's own records among them are
+    /// applied again, which leaves the indexes as they were.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        let mut appended = Vec::new();
+        let checksum = self.header.checksum;
+        self.end = ActionKV::for_each_record(&mut *self.storage, checksum, self.end, |pos, kv| {
+            appended.push((pos, kv));
+        })?;
+        // Another writer may have finished what looked like a torn record.
+        let len = self.storage.len()?;
+        self.torn_tail = if len > self.end { Some(len) } else { None };
+        for (pos, kv) in appended {
+            if kv.key == INDEXES_KEY {
+                self.apply_index_definitions(&kv.value)?;
+            } else {
+                self.apply(&kv.key, &kv.value, pos);
+            }
+        }
+        Ok(())
+    }
+
+    // Visits every record in the log from `start`, in the order written, and
+    // returns the offset just past the last one. An incomplete record or
+    // transaction at the end of the log ends the walk.
+    fn for_each_record<F>(
+        storage: &mut dyn Storage,
+        checksum: ChecksumAlgorithm,
+        start: u64,
+        mut visit: F,
+    ) -> io::Result<u64>
+    where
+        F: FnMut(u64, KeyValuePair),
+    {
+        let mut f = BufReader::new(StorageReader::new(storage, start));
+        let mut pos = start;
         loop {
-            let current_pos = f.seek(SeekFrom::Current(0))?;
-            let maybe_kv = ActionKV::process_record(&mut f);
-            let kv = match maybe_kv {
-                Ok(kv) => kv,
+            let maybe_batch = ActionKV::process_batch(&mut f, checksum, &mut pos);
+            let batch = match maybe_batch {
+                Ok(batch) => batch,
                 Err(err) => {
                     match err.kind() {
                         io::ErrorKind::UnexpectedEof => {
@@ -80,15 +389,51 @@ impl ActionKV {
                     }
                 }
             };
-            self.index.insert(kv.key, current_pos);
+            for (record_pos, kv) in batch {
+                visit(record_pos, kv);
+            }
         }
-        Ok(())
+        Ok(pos)
+    }
+
+    // Reads the next record, or all of a transaction's records if it starts
+    // with a `TXN_MARKER`, along with their offsets. `pos` is only advanced
+    // once everything has been read, so on error it still points at the
+    // start of what failed.
+    fn process_batch<R: Read>(
+        f: &mut R,
+        checksum: ChecksumAlgorithm,
+        pos: &mut u64,
+    ) -> io::Result<Vec<(u64, KeyValuePair)>> {
+        let start = *pos;
+        let first = ActionKV::process_record(f, checksum)?;
+        let mut next = start + record_len(&first);
+        if first.key != TXN_MARKER {
+            *pos = next;
+            return Ok(vec![(start, first)]);
+        }
+        let count = (&first.value[..]).read_u32::<LittleEndian>()?;
+        let mut batch = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let kv = ActionKV::process_record(f, checksum)?;
+            let len = record_len(&kv);
+            batch.push((next, kv));
+            next += len;
+        }
+        *pos = next;
+        Ok(batch)
     }
 
     // Return type allows possibility of an I/O error as well as missing values.
     pub fn get(&mut self, key: &ByteStr) -> io::Result<Option<ByteString>> {
+        if !self.bloom_check(key) {
+            return Ok(None);
+        }
         let pos = match self.index.get(key) {
-            None => return Ok(None),
+            None => {
+                self.bloom_miss();
+                return Ok(None);
+            }
             Some(pos) => *pos,
         };
         let kv = self.get_at(pos)?;
@@ -96,71 +441,137 @@ impl ActionKV {
     }
 
     pub fn get_at(&mut self, position: u64) -> io::Result<KeyValuePair> {
-        let mut f = BufReader::new(&mut self.f);
-        f.seek(SeekFrom::Start(position))?;
-        let kv = ActionKV::process_record(&mut f)?;
+        let mut f = BufReader::new(StorageReader::new(&mut *self.storage, position));
+        let kv = ActionKV::process_record(&mut f, self.header.checksum)?;
         Ok(kv)
     }
 
+    /// Looks up the latest record for `target` and returns it with its
+    /// offset. Like `get`, it only looks in the index, so it finds nothing
+    /// until `load` has been called. Use `scan` to search by anything other
+    /// than an exact key.
     pub fn find(&mut self, target: &ByteStr) -> io::Result<Option<(u64, ByteString)>> {
-        let mut f = BufReader::new(&mut self.f);
-        let mut found: Option<(u64, ByteString)> = None;
-        loop {
-            let pos = f.seek(SeekFrom::Current(0))?;
-            let maybe_kv = ActionKV::process_record(&mut f);
-            let kv = match maybe_kv {
-                Ok(kv) => kv,
-                Err(err) => {
-                    match err.kind() {
-                        io::ErrorKind::UnexpectedEof => {
-                            break; // expected
-                        }
-                        _ => return Err(err),
-                    }
-                }
-            };
-            if kv.key == target {
-                found = Some((pos, kv.value));
-            }
-            // Important to keep logging until the end of the file,
-            // in case the key has been overwritten.
+        if !self.bloom_check(target) {
+            return Ok(None);
         }
-        Ok(found)
+        let pos = match self.index.get(target) {
+            None => {
+                self.bloom_miss();
+                return Ok(None);
+            }
+            Some(&pos) => pos,
+        };
+        let kv = self.get_at(pos)?;
+        Ok(Some((pos, kv.value)))
+    }
+
+    /// Walks the whole log and reports how much of it is still live.
+    pub fn stats(&mut self) -> io::Result<StoreStats> {
+        self.stats_excluding(&[])
+    }
+
+    /// Like `stats`, but leaves out the records for `keys`, such as ones an
+    /// application keeps its own bookkeeping under.
+    pub fn stats_excluding(&mut self, keys: &[&ByteStr]) -> io::Result<StoreStats> {
+        let mut collector = StatsCollector::new(LARGEST_VALUES_REPORTED);
+        let checksum = self.header.checksum;
+        ActionKV::for_each_record(&mut *self.storage, checksum, HEADER_LEN, |pos, kv| {
+            if !keys.contains(&&kv.key[..]) && !ActionKV::is_reserved_key(&kv.key) {
+                collector.add(pos, kv)
+            }
+        })?;
+        let total_bytes = self.storage.len()? - HEADER_LEN;
+        let mut stats = collector.finish(total_bytes);
+        stats.bloom = self.bloom_report();
+        Ok(stats)
+    }
+
+    /// Whether the log keeps `key` for its own records. Writing it is
+    /// refused with an `InvalidInput` error.
+    pub fn is_reserved_key(key: &ByteStr) -> bool {
+        key == TXN_MARKER || key == INDEXES_KEY
     }
 
     pub fn insert(&mut self, key: &ByteStr, value: &ByteStr) -> io::Result<()> {
+        check_key(key)?;
         let pos = self.insert_but_ignore_index(key, value)?;
-        self.index.insert(key.to_vec(), pos);
+        self.apply(key, value, pos);
         Ok(())
     }
 
+    // Brings the in-memory indexes in line with a record at `pos`.
+    fn apply(&mut self, key: &ByteStr, value: &ByteStr, pos: u64) {
+        self.index.insert(key.to_vec(), pos);
+        if let Some(Bloom { filter: Some(filter), .. }) = self.bloom.as_mut() {
+            filter.insert(key);
+        }
+        for s in self.secondary.values_mut() {
+            s.update(key, value);
+        }
+    }
+
     fn insert_but_ignore_index(
         &mut self,
         key: &ByteStr,
         value: &ByteStr
     ) -> io::Result<u64> {
-        let mut f = BufWriter::new(&mut self.f);
-        let key_len = key.len();
-        let value_len = value.len();
-        let mut tmp = ByteString::with_capacity(key_len + value_len);
-        for byte in key {
-            tmp.push(*byte);
-        }
-        for byte in value {
-            tmp.push(*byte);
-        }
-        let mut digest = CRC32.digest();
-        digest.update(&tmp);
-        let checksum = digest.finalize();
-
-        let next_byte = SeekFrom::End(0);
-        let current_pos = f.seek(SeekFrom::Current(0))?;
-        f.seek(next_byte)?;
-        f.write_u32::<LittleEndian>(checksum)?;
-        f.write_u32::<LittleEndian>(key_len as u32)?;
-        f.write_u32::<LittleEndian>(value_len as u32)?;
-        f.write_all(&tmp)?;
-        Ok(current_pos)
+        let mut buf = ByteString::new();
+        encode_record(key, value, self.header.checksum, &mut buf);
+        self.append(&buf)
+    }
+
+    // Writes `buf` to the end of the log in one go and returns where it
+    // starts. If the write fails part way, whatever did make it is cut off
+    // again, so that later appends don't land behind a torn record. Should
+    // that fail as well, nothing more is written until a later call manages
+    // to cut it off.
+    fn append(&mut self, buf: &ByteStr) -> io::Result<u64> {
+        if self.torn_tail.is_some() {
+            self.cut_torn_tail()?;
+        }
+        match self.storage.append(buf) {
+            Ok(current_pos) => {
+                // Records that other handles wrote since this one last
+                // looked are left for `refresh` to find, which it wouldn't
+                // if `end` skipped over them.
+                if current_pos == self.end {
+                    self.end = current_pos + buf.len() as u64;
+                }
+                Ok(current_pos)
+            }
+            Err(err) => {
+                // `refresh` will find the tear, if anything was written.
+                self.torn_tail = Some(self.end);
+                // The original error is the one worth reporting.
+                let _ = self.cut_torn_tail();
+                Err(err)
+            }
+        }
+    }
+
+    // Cuts a torn record off the end of the log. Other handles may have
+    // written to the log since the tear was found, or finished the record,
+    // so it is read again first and only what still doesn't parse as a
+    // complete record is cut off, and then only if nothing has been
+    // appended in the meantime.
+    fn cut_torn_tail(&mut self) -> io::Result<()> {
+        loop {
+            self.refresh()?;
+            let len = match self.torn_tail {
+                None => return Ok(()),
+                Some(len) => len,
+            };
+            if self.storage.len()? == len {
+                self.storage.truncate(self.end)?;
+                self.torn_tail = None;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Flushes the log to durable storage.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.storage.sync()
     }
 
     #[inline]
@@ -174,3 +585,51 @@ impl ActionKV {
     }
 }
 
+// `Path::with_extension` would replace an existing extension rather than
+// add to it, so `store.db` would become `store.bloom`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut p: OsString = path.to_path_buf().into_os_string();
+    p.push(suffix);
+    PathBuf::from(p)
+}
+
+fn encode_record(
+    key: &ByteStr,
+    value: &ByteStr,
+    checksum: ChecksumAlgorithm,
+    buf: &mut ByteString,
+) {
+    let key_len = key.len();
+    let value_len = value.len();
+    let mut tmp = ByteString::with_capacity(key_len + value_len);
+    for byte in key {
+        tmp.push(*byte);
+    }
+    for byte in value {
+        tmp.push(*byte);
+    }
+    let checksum = checksum.checksum(&tmp);
+
+    // Writing to a `Vec` can't fail.
+    buf.write_u32::<LittleEndian>(checksum).unwrap();
+    buf.write_u32::<LittleEndian>(key_len as u32).unwrap();
+    buf.write_u32::<LittleEndian>(value_len as u32).unwrap();
+    buf.extend_from_slice(&tmp);
+}
+
+// A record under a reserved key would be read back as one of the log's own,
+// and take the records after it down with it.
+fn check_key(key: &ByteStr) -> io::Result<()> {
+    if ActionKV::is_reserved_key(key) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("key {:?} is reserved", String::from_utf8_lossy(key)),
+        ));
+    }
+    Ok(())
+}
+
+#[inline]
+fn record_len(kv: &KeyValuePair) -> u64 {
+    RECORD_HEADER_LEN + kv.key.len() as u64 + kv.value.len() as u64
+}
@@ -0,0 +1,101 @@
+use std::io;
+
+use regex::bytes::Regex;
+
+use crate::{ActionKV, ByteStr, ByteString, KeyValuePair};
+
+type KeyPredicate = Box<dyn Fn(&ByteStr) -> bool>;
+
+/// Which records `ActionKV::scan` returns. Every condition that is set must
+/// hold; an empty filter matches every live key.
+#[derive(Default)]
+pub struct ScanFilter {
+    key: Option<KeyPredicate>,
+    key_regex: Option<Regex>,
+    value_contains: Option<ByteString>,
+}
+
+impl ScanFilter {
+    pub fn new() -> ScanFilter {
+        ScanFilter::default()
+    }
+
+    pub fn key<F>(mut self, predicate: F) -> ScanFilter
+    where
+        F: Fn(&ByteStr) -> bool + 'static,
+    {
+        self.key = Some(Box::new(predicate));
+        self
+    }
+
+    /// Keeps keys that `re` matches anywhere; anchor it with `^` and `$` to
+    /// match whole keys.
+    pub fn key_regex(mut self, re: Regex) -> ScanFilter {
+        self.key_regex = Some(re);
+        self
+    }
+
+    pub fn value_contains(mut self, needle: &ByteStr) -> ScanFilter {
+        self.value_contains = Some(needle.to_vec());
+        self
+    }
+
+    fn matches_key(&self, key: &ByteStr) -> bool {
+        self.key.as_ref().is_none_or(|f| f(key))
+            && self.key_regex.as_ref().is_none_or(|re| re.is_match(key))
+    }
+
+    fn matches_value(&self, value: &ByteStr) -> bool {
+        match &self.value_contains {
+            None => true,
+            Some(needle) => memchr::memmem::find(value, needle).is_some(),
+        }
+    }
+}
+
+/// The records a scan matched, with their offsets, in the order they were
+/// written. Made by `ActionKV::scan`.
+pub struct Scan<'a> {
+    store: &'a mut ActionKV,
+    filter: &'a ScanFilter,
+    offsets: std::vec::IntoIter<u64>,
+}
+
+impl Iterator for Scan<'_> {
+    type Item = io::Result<(u64, KeyValuePair)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for pos in self.offsets.by_ref() {
+            let kv = match self.store.get_at(pos) {
+                Ok(kv) => kv,
+                Err(err) => return Some(Err(err)),
+            };
+            if !kv.value.is_empty() && self.filter.matches_value(&kv.value) {
+                return Some(Ok((pos, kv)));
+            }
+        }
+        None
+    }
+}
+
+impl ActionKV {
+    /// Streams the live records that pass `filter`. Keys are matched against
+    /// the index, so only the latest record for each matching key is read
+    /// from the log, and deleted keys are skipped. Reflects the index as it
+    /// was when the scan started.
+    pub fn scan<'a>(&'a mut self, filter: &'a ScanFilter) -> Scan<'a> {
+        let mut offsets: Vec<u64> = self
+            .index
+            .iter()
+            .filter(|(key, _)| filter.matches_key(key))
+            .map(|(_, &pos)| pos)
+            .collect();
+        // Reading in log order keeps the reads close to sequential.
+        offsets.sort_unstable();
+        Scan {
+            store: self,
+            filter,
+            offsets: offsets.into_iter(),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+
+use serde_json::Value;
+
+use crate::{encode_record, ActionKV, ByteStr, ByteString, HEADER_LEN, INDEXES_KEY};
+
+/// Maps the value found at a JSON pointer inside each record's value back to
+/// the keys holding it. Values that aren't JSON, or don't contain the
+/// pointer, are simply not indexed.
+///
+/// Field values are compared as JSON, so `"42"` and `42` differ, but numbers
+/// are compared by value: `1`, `1.0` and `1e0` are the same.
+#[derive(Debug)]
+pub struct SecondaryIndex {
+    pointer: String,
+    // Field values are keyed by the JSON text of `normalize(value)`.
+    entries: HashMap<String, BTreeSet<ByteString>>,
+    by_key: HashMap<ByteString, String>,
+}
+
+impl SecondaryIndex {
+    fn new(pointer: &str) -> SecondaryIndex {
+        SecondaryIndex {
+            pointer: pointer.to_string(),
+            entries: HashMap::new(),
+            by_key: HashMap::new(),
+        }
+    }
+
+    pub fn pointer(&self) -> &str {
+        &self.pointer
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.by_key.clear();
+    }
+
+    pub(crate) fn update(&mut self, key: &ByteStr, value: &ByteStr) {
+        if let Some(old) = self.by_key.remove(key) {
+            if let Some(keys) = self.entries.get_mut(&old) {
+                keys.remove(key);
+                if keys.is_empty() {
+                    self.entries.remove(&old);
+                }
+            }
+        }
+        let field = match serde_json::from_slice::<Value>(value) {
+            Ok(doc) => match doc.pointer(&self.pointer) {
+                Some(field) => normalize(field).to_string(),
+                None => return,
+            },
+            Err(_) => return,
+        };
+        self.entries
+            .entry(field.clone())
+            .or_default()
+            .insert(key.to_vec());
+        self.by_key.insert(key.to_vec(), field);
+    }
+
+    fn find(&self, value: &Value) -> Vec<ByteString> {
+        match self.entries.get(&normalize(value).to_string()) {
+            None => Vec::new(),
+            Some(keys) => keys.iter().cloned().collect(),
+        }
+    }
+}
+
+impl ActionKV {
+    /// Declares an index named `name` over the field at `pointer` (for
+    /// example `/user/email`) and builds it from the log. Indexes are kept up
+    /// to date by `insert`, `update` and `delete`. The definition is written
+    /// to the log as well, so `load` builds the index again when the log is
+    /// next opened.
+    pub fn create_index(&mut self, name: &str, pointer: &str) -> io::Result<()> {
+        if !pointer.is_empty() && !pointer.starts_with('/') {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a JSON pointer", pointer),
+            ));
+        }
+        let mut index = SecondaryIndex::new(pointer);
+        let checksum = self.header.checksum;
+        ActionKV::for_each_record(&mut *self.storage, checksum, HEADER_LEN, |_, kv| {
+            if !ActionKV::is_reserved_key(&kv.key) {
+                index.update(&kv.key, &kv.value)
+            }
+        })?;
+        let unchanged = self.secondary.get(name).is_some_and(|s| s.pointer == pointer);
+        self.secondary.insert(name.to_string(), index);
+        if unchanged {
+            return Ok(());
+        }
+        self.write_index_definitions()
+    }
+
+    /// Removes the index named `name` and its definition in the log.
+    pub fn drop_index(&mut self, name: &str) -> io::Result<Option<SecondaryIndex>> {
+        let index = self.secondary.remove(name);
+        if index.is_some() {
+            self.write_index_definitions()?;
+        }
+        Ok(index)
+    }
+
+    pub fn secondary_index(&self, name: &str) -> Option<&SecondaryIndex> {
+        self.secondary.get(name)
+    }
+
+    fn write_index_definitions(&mut self) -> io::Result<()> {
+        let definitions: BTreeMap<&str, &str> = self
+            .secondary
+            .iter()
+            .map(|(name, index)| (name.as_str(), index.pointer.as_str()))
+            .collect();
+        let value = serde_json::to_vec(&definitions)?;
+        let mut buf = ByteString::new();
+        encode_record(INDEXES_KEY, &value, self.header.checksum, &mut buf);
+        self.append(&buf)?;
+        Ok(())
+    }
+
+    // Brings the indexes in line with definitions read from the log. Ones
+    // that are new are built from the latest record of each key.
+    pub(crate) fn apply_index_definitions(&mut self, value: &ByteStr) -> io::Result<()> {
+        let definitions: BTreeMap<String, String> = serde_json::from_slice(value)?;
+        self.secondary
+            .retain(|name, index| definitions.get(name) == Some(&index.pointer));
+        for (name, pointer) in definitions {
+            if self.secondary.contains_key(&name) {
+                continue;
+            }
+            let mut index = SecondaryIndex::new(&pointer);
+            let positions: Vec<(ByteString, u64)> =
+                self.index.iter().map(|(k, &pos)| (k.clone(), pos)).collect();
+            for (key, pos) in positions {
+                index.update(&key, &self.get_at(pos)?.value);
+            }
+            self.secondary.insert(name, index);
+        }
+        Ok(())
+    }
+
+    /// Keys whose value has `value` at the pointer of index `name`, in
+    /// ascending order.
+    pub fn find_by(&self, name: &str, value: &Value) -> io::Result<Vec<ByteString>> {
+        match self.secondary.get(name) {
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no index named {}", name),
+            )),
+            Some(index) => Ok(index.find(value)),
+        }
+    }
+}
+
+// Numbers that are whole and small enough to be exact as a float are written
+// as integers, so the same number has the same text however it was written.
+fn normalize(value: &Value) -> Value {
+    const EXACT: f64 = (1u64 << 53) as f64;
+    match value {
+        Value::Number(n) if n.is_f64() => match n.as_f64() {
+            Some(f) if f.fract() == 0.0 && f.abs() < EXACT => Value::from(f as i64),
+            _ => value.clone(),
+        },
+        Value::Array(items) => Value::Array(items.iter().map(normalize).collect()),
+        Value::Object(fields) => Value::Object(
+            fields.iter().map(|(k, v)| (k.clone(), normalize(v))).collect(),
+        ),
+        _ => value.clone(),
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::{record_len, BloomReport, ByteString, KeyValuePair};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoreStats {
+    /// Keys whose latest record is not a tombstone.
+    pub live_keys: u64,
+    pub total_records: u64,
+    /// Records that have been superseded by a later record for the same key.
+    pub dead_records: u64,
+    /// Records with an empty value, which is how `delete` is written.
+    pub tombstones: u64,
+    /// On-disk size of the records that `live_keys` point to.
+    pub live_bytes: u64,
+    /// Size of the log, not counting the file header.
+    pub total_bytes: u64,
+    /// Share of the log that compaction would reclaim, from 0.0 to 1.0.
+    pub fragmentation: f64,
+    /// Largest live values, biggest first.
+    pub largest_values: Vec<ValueSize>,
+    /// Only there when a Bloom filter is enabled and built.
+    pub bloom: Option<BloomReport>,
+}
+
+impl fmt::Display for StoreStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "live keys:      {}", self.live_keys)?;
+        writeln!(f, "total records:  {}", self.total_records)?;
+        writeln!(f, "dead records:   {}", self.dead_records)?;
+        writeln!(f, "tombstones:     {}", self.tombstones)?;
+        writeln!(f, "live bytes:     {}", self.live_bytes)?;
+        writeln!(f, "total bytes:    {}", self.total_bytes)?;
+        writeln!(f, "fragmentation:  {:.1}%", self.fragmentation * 100.0)?;
+        writeln!(f, "largest values:")?;
+        for v in &self.largest_values {
+            // Debug print required to print arbitrary bytes.
+            writeln!(f, "  {:?} {} bytes at {}", v.key, v.len, v.position)?;
+        }
+        if let Some(bloom) = &self.bloom {
+            writeln!(f, "bloom filter:   {} bits, {} hashes", bloom.num_bits, bloom.num_hashes)?;
+            writeln!(
+                f,
+                "  false positive rate {:.2}% (sized for {:.2}%)",
+                bloom.estimated_false_positive_rate * 100.0,
+                bloom.target_false_positive_rate * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ValueSize {
+    pub key: ByteString,
+    pub position: u64,
+    pub len: u64,
+}
+
+struct Latest {
+    position: u64,
+    value_len: u64,
+    record_len: u64,
+}
+
+pub(crate) struct StatsCollector {
+    latest: HashMap<ByteString, Latest>,
+    total_records: u64,
+    tombstones: u64,
+    largest: usize,
+}
+
+impl StatsCollector {
+    pub fn new(largest: usize) -> StatsCollector {
+        StatsCollector {
+            latest: HashMap::new(),
+            total_records: 0,
+            tombstones: 0,
+            largest,
+        }
+    }
+
+    pub fn add(&mut self, position: u64, kv: KeyValuePair) {
+        self.total_records += 1;
+        if kv.value.is_empty() {
+            self.tombstones += 1;
+        }
+        let value_len = kv.value.len() as u64;
+        let record_len = record_len(&kv);
+        self.latest.insert(kv.key, Latest { position, value_len, record_len });
+    }
+
+    pub fn finish(self, total_bytes: u64) -> StoreStats {
+        let dead_records = self.total_records - self.latest.len() as u64;
+        let mut live_keys = 0;
+        let mut live_bytes = 0;
+        let mut largest_values = Vec::new();
+        for (key, latest) in self.latest {
+            if latest.value_len == 0 {
+                continue;
+            }
+            live_keys += 1;
+            live_bytes += latest.record_len;
+            largest_values.push(ValueSize {
+                key,
+                position: latest.position,
+                len: latest.value_len,
+            });
+        }
+        largest_values.sort_by(|a, b| b.len.cmp(&a.len).then_with(|| a.key.cmp(&b.key)));
+        largest_values.truncate(self.largest);
+        let fragmentation = if total_bytes == 0 {
+            0.0
+        } else {
+            1.0 - live_bytes as f64 / total_bytes as f64
+        };
+        StoreStats {
+            live_keys,
+            total_records: self.total_records,
+            dead_records,
+            tombstones: self.tombstones,
+            live_bytes,
+            total_bytes,
+            fragmentation,
+            largest_values,
+            bloom: None,
+        }
+    }
+}
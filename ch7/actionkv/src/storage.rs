@@ -0,0 +1,259 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, prelude::*, SeekFrom};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Where an `ActionKV` log lives. Records are only ever added at the end, so
+/// this is all the log needs.
+pub trait Storage: fmt::Debug + Send {
+    /// Writes all of `buf` at the end and returns the offset it starts at.
+    fn append(&mut self, buf: &[u8]) -> io::Result<u64>;
+
+    /// Reads from `pos` into `buf`, returning how many bytes were read. Zero
+    /// means `pos` is at or past the end.
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<usize>;
+
+    fn len(&mut self) -> io::Result<u64>;
+
+    fn is_empty(&mut self) -> io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Makes everything appended so far durable.
+    fn sync(&mut self) -> io::Result<()>;
+
+    fn truncate(&mut self, len: u64) -> io::Result<()>;
+}
+
+/// Adapts a `Storage` to `Read`, starting at a given offset.
+pub struct StorageReader<'a> {
+    storage: &'a mut dyn Storage,
+    pos: u64,
+}
+
+impl<'a> StorageReader<'a> {
+    pub fn new(storage: &'a mut dyn Storage, pos: u64) -> StorageReader<'a> {
+        StorageReader { storage, pos }
+    }
+}
+
+impl Read for StorageReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.storage.read_at(self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+#[derive(Debug)]
+pub struct FileStorage {
+    f: File,
+}
+
+impl FileStorage {
+    pub fn open(path: &Path) -> io::Result<FileStorage> {
+        let f = OpenOptions::new()
+            .read(true)
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(FileStorage { f })
+    }
+}
+
+impl Storage for FileStorage {
+    fn append(&mut self, buf: &[u8]) -> io::Result<u64> {
+        let pos = self.f.seek(SeekFrom::End(0))?;
+        self.f.write_all(buf)?;
+        Ok(pos)
+    }
+
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.f.seek(SeekFrom::Start(pos))?;
+        self.f.read(buf)
+    }
+
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.f.metadata()?.len())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        self.f.sync_data()
+    }
+
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.f.set_len(len)
+    }
+}
+
+/// Keeps the log in memory. Clones share the same bytes, so a test can hand
+/// one clone to an `ActionKV` and "reopen" the log later from another.
+#[derive(Debug, Clone, Default)]
+pub struct MemStorage {
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MemStorage {
+    pub fn new() -> MemStorage {
+        MemStorage::default()
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> MemStorage {
+        MemStorage {
+            data: Arc::new(Mutex::new(bytes)),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.data.lock().unwrap().clone()
+    }
+}
+
+impl Storage for MemStorage {
+    fn append(&mut self, buf: &[u8]) -> io::Result<u64> {
+        let mut data = self.data.lock().unwrap();
+        let pos = data.len() as u64;
+        data.extend_from_slice(buf);
+        Ok(pos)
+    }
+
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.data.lock().unwrap();
+        if pos >= data.len() as u64 {
+            return Ok(0);
+        }
+        let available = &data[pos as usize..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        Ok(n)
+    }
+
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.data.lock().unwrap().len() as u64)
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        self.data.lock().unwrap().truncate(len as usize);
+        Ok(())
+    }
+}
+
+/// What a `FaultyStorage` should do instead of the next operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Fail with an error of this kind without touching the storage.
+    Error(io::ErrorKind),
+    /// Write only this many bytes of the next append, then fail, as a crash
+    /// or full disk part way through a write would.
+    ShortWrite(usize),
+}
+
+#[derive(Debug, Default)]
+struct Faults {
+    append: Option<Fault>,
+    // How many reads to let through first, and the error for the one after.
+    read: Option<(usize, io::ErrorKind)>,
+    sync: Option<io::ErrorKind>,
+    truncate: Option<io::ErrorKind>,
+}
+
+/// Wraps another `Storage` and fails operations on demand, to exercise error
+/// paths. Each injected fault fires once. Clones share the same faults, so a
+/// test can keep one to arm faults on a storage an `ActionKV` owns.
+#[derive(Debug, Clone)]
+pub struct FaultyStorage<S> {
+    inner: S,
+    faults: Arc<Mutex<Faults>>,
+}
+
+impl<S: Storage> FaultyStorage<S> {
+    pub fn new(inner: S) -> FaultyStorage<S> {
+        FaultyStorage {
+            inner,
+            faults: Arc::new(Mutex::new(Faults::default())),
+        }
+    }
+
+    pub fn fail_next_append(&self, fault: Fault) {
+        self.faults.lock().unwrap().append = Some(fault);
+    }
+
+    pub fn fail_next_read(&self, kind: io::ErrorKind) {
+        self.fail_read_after(0, kind);
+    }
+
+    /// Lets `reads` reads through, then fails the one after that.
+    pub fn fail_read_after(&self, reads: usize, kind: io::ErrorKind) {
+        self.faults.lock().unwrap().read = Some((reads, kind));
+    }
+
+    pub fn fail_next_sync(&self, kind: io::ErrorKind) {
+        self.faults.lock().unwrap().sync = Some(kind);
+    }
+
+    pub fn fail_next_truncate(&self, kind: io::ErrorKind) {
+        self.faults.lock().unwrap().truncate = Some(kind);
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+fn injected(kind: io::ErrorKind) -> io::Error {
+    io::Error::new(kind, "injected fault")
+}
+
+impl<S: Storage> Storage for FaultyStorage<S> {
+    fn append(&mut self, buf: &[u8]) -> io::Result<u64> {
+        let fault = self.faults.lock().unwrap().append.take();
+        match fault {
+            None => self.inner.append(buf),
+            Some(Fault::Error(kind)) => Err(injected(kind)),
+            Some(Fault::ShortWrite(n)) => {
+                self.inner.append(&buf[..n.min(buf.len())])?;
+                Err(injected(io::ErrorKind::WriteZero))
+            }
+        }
+    }
+
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        {
+            let mut faults = self.faults.lock().unwrap();
+            match faults.read {
+                Some((0, kind)) => {
+                    faults.read = None;
+                    return Err(injected(kind));
+                }
+                Some((reads, kind)) => faults.read = Some((reads - 1, kind)),
+                None => {}
+            }
+        }
+        self.inner.read_at(pos, buf)
+    }
+
+    fn len(&mut self) -> io::Result<u64> {
+        self.inner.len()
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        let fault = self.faults.lock().unwrap().sync.take();
+        match fault {
+            None => self.inner.sync(),
+            Some(kind) => Err(injected(kind)),
+        }
+    }
+
+    fn truncate(&mut self, len: u64) -> io::Result<()> {
+        let fault = self.faults.lock().unwrap().truncate.take();
+        match fault {
+            None => self.inner.truncate(len),
+            Some(kind) => Err(injected(kind)),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::io;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+
+use crate::{check_key, encode_record, ActionKV, ByteStr, ByteString, TXN_MARKER};
+
+/// A set of writes that is committed all at once, or not at all if any key
+/// it read has been written since.
+///
+/// Reads go through `get`, which remembers the offset of the record each key
+/// was read from (or that it was absent). `ActionKV::commit` checks those
+/// offsets against the log before writing anything.
+#[derive(Debug, Default)]
+pub struct Transaction {
+    reads: HashMap<ByteString, Option<u64>>,
+    writes: Vec<(ByteString, ByteString)>,
+}
+
+impl Transaction {
+    pub fn new() -> Transaction {
+        Transaction::default()
+    }
+
+    /// Reads `key` as of the start of the transaction, or as written by this
+    /// transaction if it already has been.
+    pub fn get(
+        &mut self,
+        store: &mut ActionKV,
+        key: &ByteStr,
+    ) -> io::Result<Option<ByteString>> {
+        if let Some((_, value)) = self.writes.iter().rev().find(|(k, _)| k == key) {
+            return Ok(Some(value.clone()));
+        }
+        let pos = store.index.get(key).copied();
+        self.reads.entry(key.to_vec()).or_insert(pos);
+        match pos {
+            None => Ok(None),
+            Some(pos) => Ok(Some(store.get_at(pos)?.value)),
+        }
+    }
+
+    pub fn insert(&mut self, key: &ByteStr, value: &ByteStr) {
+        self.writes.push((key.to_vec(), value.to_vec()));
+    }
+
+    #[inline]
+    pub fn update(&mut self, key: &ByteStr, value: &ByteStr) {
+        self.insert(key, value)
+    }
+
+    #[inline]
+    pub fn delete(&mut self, key: &ByteStr) {
+        self.insert(key, b"")
+    }
+}
+
+impl ActionKV {
+    /// Starts a transaction. Call `refresh` first if other processes may be
+    /// writing to the log, so that reads start from its latest state.
+    pub fn transaction(&self) -> Transaction {
+        Transaction::new()
+    }
+
+    /// Writes the transaction's changes if none of the keys it read have
+    /// changed since, returning `false` (and writing nothing) if one has.
+    /// The writes go to the log as a single block that `load` either applies
+    /// in full or, if it was cut short, not at all.
+    ///
+    /// Writing a reserved key fails the whole transaction with an
+    /// `InvalidInput` error.
+    ///
+    /// The check and the write are not protected against another process
+    /// appending in between; callers sharing a file across processes still
+    /// need a lock around `commit`.
+    pub fn commit(&mut self, txn: Transaction) -> io::Result<bool> {
+        for (key, _) in &txn.writes {
+            check_key(key)?;
+        }
+        self.refresh()?;
+        for (key, seen) in &txn.reads {
+            if self.index.get(key).copied() != *seen {
+                return Ok(false);
+            }
+        }
+        if txn.writes.is_empty() {
+            return Ok(true);
+        }
+
+        let mut count = ByteString::with_capacity(4);
+        count.write_u32::<LittleEndian>(txn.writes.len() as u32)?;
+        let mut buf = ByteString::new();
+        let checksum = self.header.checksum;
+        encode_record(TXN_MARKER, &count, checksum, &mut buf);
+        let mut offsets = Vec::with_capacity(txn.writes.len());
+        for (key, value) in &txn.writes {
+            offsets.push(buf.len() as u64);
+            encode_record(key, value, checksum, &mut buf);
+        }
+        let start = self.append(&buf)?;
+        for ((key, value), offset) in txn.writes.iter().zip(offsets) {
+            self.apply(key, value, start + offset);
+        }
+        Ok(true)
+    }
+
+    /// Sets `key` to `new` only if its current value is `expected`, where
+    /// `None` means the key has never been written. Returns whether the swap
+    /// happened.
+    pub fn compare_and_swap(
+        &mut self,
+        key: &ByteStr,
+        expected: Option<&ByteStr>,
+        new: &ByteStr,
+    ) -> io::Result<bool> {
+        self.refresh()?;
+        let mut txn = self.transaction();
+        let current = txn.get(self, key)?;
+        if current.as_deref() != expected {
+            return Ok(false);
+        }
+        txn.insert(key, new);
+        self.commit(txn)
+    }
+}
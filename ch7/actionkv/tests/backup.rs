@@ -0,0 +1,115 @@
+use std::fs::{self, OpenOptions};
+use std::io;
+
+use libactionkv::{ActionKV, FaultyStorage, MemStorage};
+
+fn store_with(path: &std::path::Path, pairs: &[(&[u8], &[u8])]) -> ActionKV {
+    let mut store = ActionKV::open(path).unwrap();
+    store.load().unwrap();
+    for (key, value) in pairs {
+        store.insert(key, value).unwrap();
+    }
+    store
+}
+
+#[test]
+fn incremental_backup_picks_up_where_the_last_one_ended() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, dest) = (dir.path().join("store.db"), dir.path().join("backup.db"));
+    let mut store = store_with(&path, &[(b"a", b"1")]);
+    let first = store.backup_to(&dest).unwrap();
+    store.insert(b"b", b"2").unwrap();
+    let second = store.backup_incremental(&dest).unwrap();
+    assert!(second > first);
+    assert_eq!(fs::read(&dest).unwrap(), fs::read(&path).unwrap());
+}
+
+#[test]
+fn incremental_backup_refuses_a_backup_that_does_not_end_on_a_record() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, dest) = (dir.path().join("store.db"), dir.path().join("backup.db"));
+    let mut store = store_with(&path, &[(b"a", b"1"), (b"b", b"2")]);
+    let end = store.backup_to(&dest).unwrap();
+    store.insert(b"c", b"3").unwrap();
+
+    let backup = OpenOptions::new().write(true).open(&dest).unwrap();
+    backup.set_len(end - 3).unwrap();
+    let err = store.backup_incremental(&dest).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(fs::metadata(&dest).unwrap().len(), end - 3);
+}
+
+#[test]
+fn incremental_backup_refuses_a_backup_that_has_diverged() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, dest) = (dir.path().join("store.db"), dir.path().join("backup.db"));
+    let mut store = store_with(&path, &[(b"a", b"1")]);
+    store.backup_to(&dest).unwrap();
+    // A record of the same size as the one the backup doesn't have yet.
+    let mut backup = ActionKV::open(&dest).unwrap();
+    backup.insert(b"x", b"9").unwrap();
+    store.insert(b"b", b"2").unwrap();
+    store.insert(b"c", b"3").unwrap();
+
+    let err = store.backup_incremental(&dest).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn failed_incremental_backup_leaves_the_backup_as_it_was() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("backup.db");
+    let mem = MemStorage::new();
+    let faulty = FaultyStorage::new(mem.clone());
+    let mut store = ActionKV::with_storage(Box::new(faulty.clone())).unwrap();
+    store.load().unwrap();
+    store.insert(b"a", b"1").unwrap();
+    let start = store.backup_to(&dest).unwrap();
+    // Big enough to take several reads to copy.
+    for key in [b"b", b"c", b"d"] {
+        store.insert(key, &[b'x'; 10_000]).unwrap();
+    }
+
+    // Fail each read in turn, including ones part way through the copy,
+    // until there are none left to fail.
+    let mut reads = 0;
+    loop {
+        faulty.fail_read_after(reads, io::ErrorKind::Other);
+        if store.backup_incremental(&dest).is_ok() {
+            break;
+        }
+        assert_eq!(fs::metadata(&dest).unwrap().len(), start, "read {}", reads);
+        reads += 1;
+    }
+    assert!(reads > 3, "only {} reads", reads);
+    assert_eq!(fs::read(&dest).unwrap(), mem.to_bytes());
+}
+
+#[test]
+fn failed_backup_leaves_no_temporary_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.db");
+    let mut store = store_with(&path, &[(b"a", b"1")]);
+    // Renaming over a directory that isn't empty fails.
+    let dest = dir.path().join("taken");
+    fs::create_dir(&dest).unwrap();
+    fs::write(dest.join("file"), b"").unwrap();
+
+    assert!(store.backup_to(&dest).is_err());
+    assert!(!dir.path().join("taken.tmp").exists());
+}
+
+#[test]
+fn restore_brings_back_the_backed_up_state() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, dest) = (dir.path().join("store.db"), dir.path().join("backup.db"));
+    let mut store = store_with(&path, &[(b"a", b"1")]);
+    store.backup_to(&dest).unwrap();
+    store.insert(b"a", b"2").unwrap();
+    store.insert(b"b", b"3").unwrap();
+
+    store.restore(&dest).unwrap();
+    assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(store.get(b"b").unwrap(), None);
+    assert!(!dir.path().join("store.db.restore").exists());
+}
@@ -0,0 +1,120 @@
+use std::fs;
+use std::time::{Duration, SystemTime};
+
+use libactionkv::{ActionKV, BloomFilter, MemStorage};
+
+fn keys(prefix: &str, n: usize) -> Vec<Vec<u8>> {
+    (0..n).map(|i| format!("{}{}", prefix, i).into_bytes()).collect()
+}
+
+#[test]
+fn filter_has_no_false_negatives_and_about_the_rate_it_was_sized_for() {
+    let mut filter = BloomFilter::new(1000, 0.01);
+    for key in keys("in", 1000) {
+        filter.insert(&key);
+    }
+    assert!(keys("in", 1000).iter().all(|k| filter.may_contain(k)));
+
+    let false_positives = keys("out", 10_000).iter().filter(|k| filter.may_contain(k)).count();
+    assert!(false_positives < 300, "{} false positives", false_positives);
+    let estimate = filter.estimated_false_positive_rate();
+    assert!(estimate > 0.0 && estimate < 0.03, "{}", estimate);
+}
+
+#[test]
+fn rate_outside_zero_to_one_is_refused() {
+    let mut store = ActionKV::with_storage(Box::new(MemStorage::new())).unwrap();
+    for rate in [0.0, 1.0, 1.5, -0.1, f64::NAN] {
+        let err = store.enable_bloom_filter(rate).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "rate {}", rate);
+    }
+    store.load().unwrap();
+    assert!(store.bloom_filter().is_none());
+}
+
+#[test]
+fn store_finds_every_key_through_the_filter() {
+    let mem = MemStorage::new();
+    let mut store = ActionKV::with_storage(Box::new(mem)).unwrap();
+    for key in keys("k", 500) {
+        store.insert(&key, b"v").unwrap();
+    }
+    store.enable_bloom_filter(0.01).unwrap();
+    store.load().unwrap();
+    // Keys written after the filter was built are added to it as well.
+    store.insert(b"late", b"v").unwrap();
+    for key in keys("k", 500) {
+        assert_eq!(store.get(&key).unwrap(), Some(b"v".to_vec()));
+    }
+    assert_eq!(store.get(b"late").unwrap(), Some(b"v".to_vec()));
+
+    let stats = store.bloom_stats().unwrap();
+    assert_eq!((stats.lookups, stats.avoided, stats.false_positives), (501, 0, 0));
+    let report = store.stats().unwrap().bloom.unwrap();
+    assert_eq!(report.target_false_positive_rate, 0.01);
+    assert_eq!(report.lookups, stats);
+}
+
+#[test]
+fn saved_filter_is_only_reused_for_the_log_it_was_built_from() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.db");
+    let other = dir.path().join("other.db");
+
+    // Two logs with the same header and the same length, but different
+    // last records, as a restored backup of the same store could be.
+    drop(ActionKV::open(&path).unwrap());
+    fs::copy(&path, &other).unwrap();
+    ActionKV::open(&path).unwrap().insert(b"a", b"1").unwrap();
+    ActionKV::open(&other).unwrap().insert(b"b", b"2").unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), fs::metadata(&other).unwrap().len());
+
+    let mut store = ActionKV::open(&path).unwrap();
+    store.enable_bloom_filter(0.01).unwrap();
+    store.load().unwrap();
+    drop(store);
+
+    let mut store = ActionKV::open(&path).unwrap();
+    store.enable_bloom_filter(0.01).unwrap();
+    assert!(store.bloom_filter().is_some());
+    drop(store);
+
+    fs::copy(&other, &path).unwrap();
+    let mut store = ActionKV::open(&path).unwrap();
+    store.enable_bloom_filter(0.01).unwrap();
+    assert!(store.bloom_filter().is_none());
+    store.load().unwrap();
+    assert_eq!(store.get(b"b").unwrap(), Some(b"2".to_vec()));
+
+    // Nor is it reused once the log has grown.
+    store.insert(b"c", b"3").unwrap();
+    drop(store);
+    let mut store = ActionKV::open(&path).unwrap();
+    store.enable_bloom_filter(0.01).unwrap();
+    assert!(store.bloom_filter().is_none());
+}
+
+#[test]
+fn saved_filter_that_still_matches_is_not_rebuilt_by_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.db");
+    let bloom = dir.path().join("store.db.bloom");
+    let mut store = ActionKV::open(&path).unwrap();
+    store.insert(b"a", b"1").unwrap();
+    store.enable_bloom_filter(0.01).unwrap();
+    store.load().unwrap();
+    drop(store);
+
+    let long_ago = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+    fs::File::options().write(true).open(&bloom).unwrap().set_modified(long_ago).unwrap();
+    let mut store = ActionKV::open(&path).unwrap();
+    store.enable_bloom_filter(0.01).unwrap();
+    store.load().unwrap();
+    assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(fs::metadata(&bloom).unwrap().modified().unwrap(), long_ago);
+
+    // Once the log has moved on, it is.
+    store.insert(b"b", b"2").unwrap();
+    store.load().unwrap();
+    assert_ne!(fs::metadata(&bloom).unwrap().modified().unwrap(), long_ago);
+}
@@ -0,0 +1,44 @@
+use std::path::Path;
+use std::process::Command;
+
+fn akv_disk(path: &Path, args: &[&str]) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_akv_disk"))
+        .arg(path)
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn akv_disk_leaves_its_index_out_of_stats_and_grep() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.db");
+    akv_disk(&path, &["insert", "a", "1"]);
+    akv_disk(&path, &["insert", "b", "2"]);
+
+    let stats = akv_disk(&path, &["stats", "--json"]);
+    let stats: serde_json::Value = serde_json::from_str(&stats).unwrap();
+    assert_eq!(stats["live_keys"], 2);
+    assert_eq!(stats["total_records"], 2);
+
+    let found = akv_disk(&path, &["grep", "."]);
+    let keys: Vec<&str> = found.lines().map(|l| l.split('\t').nth(1).unwrap()).collect();
+    assert_eq!(keys, ["a", "b"]);
+}
+
+#[test]
+fn grep_can_search_values_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("store.db");
+    akv_disk(&path, &["insert", "a", "red apple"]);
+    akv_disk(&path, &["insert", "b", "green pear"]);
+    akv_disk(&path, &["insert", "c", "red cherry"]);
+
+    let found = akv_disk(&path, &["grep", "--value", "red"]);
+    let keys: Vec<&str> = found.lines().map(|l| l.split('\t').nth(1).unwrap()).collect();
+    assert_eq!(keys, ["a", "c"]);
+    let found = akv_disk(&path, &["grep", "^c$", "red"]);
+    assert_eq!(found.lines().count(), 1);
+}
@@ -0,0 +1,75 @@
+use std::fs;
+use std::io;
+
+use libactionkv::{
+    ActionKV, ChecksumAlgorithm, FileHeader, MemStorage, FLAG_COMPRESSED, FORMAT_VERSION,
+    HEADER_LEN,
+};
+
+fn open_bytes(bytes: Vec<u8>) -> io::Result<ActionKV> {
+    ActionKV::with_storage(Box::new(MemStorage::from_bytes(bytes)))
+}
+
+#[test]
+fn new_store_gets_a_header() {
+    let mem = MemStorage::new();
+    let store = ActionKV::with_storage(Box::new(mem.clone())).unwrap();
+    let bytes = mem.to_bytes();
+    assert_eq!(bytes.len() as u64, HEADER_LEN);
+    assert_eq!(&bytes[..8], b"ACTIONKV");
+    assert_eq!(FileHeader::from_bytes(&bytes).unwrap(), *store.header());
+    assert_eq!(store.header().version, FORMAT_VERSION);
+}
+
+#[test]
+fn rejects_files_that_are_not_actionkv_logs() {
+    let err = open_bytes(b"just some text that is long enough".to_vec()).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert!(err.to_string().contains("migrate"), "{}", err);
+}
+
+#[test]
+fn rejects_damaged_or_unsupported_headers() {
+    let good = FileHeader::new(ChecksumAlgorithm::default()).to_bytes();
+
+    let mut damaged = good.clone();
+    damaged[20] ^= 1;
+    assert_eq!(open_bytes(damaged).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+    let mut newer = FileHeader::new(ChecksumAlgorithm::default());
+    newer.version = FORMAT_VERSION + 1;
+    let err = open_bytes(newer.to_bytes()).unwrap_err();
+    assert!(err.to_string().contains("newer"), "{}", err);
+
+    let mut compressed = FileHeader::new(ChecksumAlgorithm::default());
+    compressed.flags = FLAG_COMPRESSED;
+    let err = open_bytes(compressed.to_bytes()).unwrap_err();
+    assert!(err.to_string().contains("features"), "{}", err);
+}
+
+#[test]
+fn migrate_adds_a_header_to_old_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("old.akv");
+    {
+        let mut store = ActionKV::open(&path).unwrap();
+        store.load().unwrap();
+        store.insert(b"a", b"1").unwrap();
+        store.insert(b"b", b"2").unwrap();
+    }
+    // Strip the header, and leave half a record behind as a crash would.
+    let bytes = fs::read(&path).unwrap();
+    let mut old = bytes[HEADER_LEN as usize..].to_vec();
+    old.extend_from_slice(&bytes[HEADER_LEN as usize..HEADER_LEN as usize + 6]);
+    fs::write(&path, &old).unwrap();
+    assert!(ActionKV::open(&path).is_err());
+
+    assert!(ActionKV::migrate(&path).unwrap());
+    assert!(!ActionKV::migrate(&path).unwrap());
+
+    let mut store = ActionKV::open(&path).unwrap();
+    store.load().unwrap();
+    assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(store.get(b"b").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(fs::metadata(&path).unwrap().len(), bytes.len() as u64);
+}
@@ -0,0 +1,177 @@
+use std::io::{prelude::*, BufReader};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+struct Server {
+    child: Child,
+    addr: String,
+    path: PathBuf,
+}
+
+impl Server {
+    fn start(name: &str) -> Server {
+        let path = std::env::temp_dir()
+            .join(format!("akv_http_{}_{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut child = Command::new(env!("CARGO_BIN_EXE_akv_http"))
+            .arg(&path)
+            .arg("127.0.0.1:0")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout: ChildStdout = child.stdout.take().unwrap();
+        let mut line = String::new();
+        BufReader::new(stdout).read_line(&mut line).unwrap();
+        let addr = line.trim().trim_start_matches("listening on ").to_string();
+        Server { child, addr, path }
+    }
+
+    fn request(&self, method: &str, target: &str, headers: &[(&str, &str)], body: &[u8]) -> Reply {
+        let mut stream = TcpStream::connect(&self.addr).unwrap();
+        let mut head = format!("{} {} HTTP/1.1\r\nHost: localhost\r\n", method, target);
+        for (name, value) in headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("Content-Length: {}\r\n\r\n", body.len()));
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw).unwrap();
+        Reply::parse(&raw)
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.path);
+        let _ = std::fs::remove_file(self.path.with_extension("db.bloom"));
+    }
+}
+
+struct Reply {
+    status: u16,
+    etag: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn parse(raw: &[u8]) -> Reply {
+        let split = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        let head = String::from_utf8(raw[..split].to_vec()).unwrap();
+        let mut lines = head.lines();
+        let status = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+        let etag = lines
+            .filter_map(|l| l.split_once(": "))
+            .find(|(name, _)| name.eq_ignore_ascii_case("etag"))
+            .map(|(_, value)| value.to_string());
+        Reply { status, etag, body: raw[split + 4..].to_vec() }
+    }
+}
+
+#[test]
+fn put_get_delete_round_trip_with_binary_values() {
+    let server = Server::start("round_trip");
+    let value = [0u8, 159, 146, 150, 255, b'\n'];
+
+    assert_eq!(server.request("GET", "/kv/bin%00key", &[], b"").status, 404);
+    let put = server.request("PUT", "/kv/bin%00key", &[], &value);
+    assert_eq!(put.status, 201);
+    assert!(put.etag.is_some());
+
+    let got = server.request("GET", "/kv/bin%00key", &[], b"");
+    assert_eq!(got.status, 200);
+    assert_eq!(got.body, value);
+    assert_eq!(got.etag, put.etag);
+
+    assert_eq!(server.request("DELETE", "/kv/bin%00key", &[], b"").status, 204);
+    assert_eq!(server.request("GET", "/kv/bin%00key", &[], b"").status, 404);
+    assert_eq!(server.request("DELETE", "/kv/bin%00key", &[], b"").status, 404);
+}
+
+#[test]
+fn oversized_bodies_are_refused_before_they_are_read() {
+    let server = Server::start("oversized");
+    let mut stream = TcpStream::connect(&server.addr).unwrap();
+    let head = "PUT /kv/big HTTP/1.1\r\nContent-Length: 18446744073709551615\r\n\r\n";
+    stream.write_all(head.as_bytes()).unwrap();
+    let mut raw = Vec::new();
+    stream.read_to_end(&mut raw).unwrap();
+    assert_eq!(Reply::parse(&raw).status, 413);
+    assert_eq!(server.request("GET", "/kv/big", &[], b"").status, 404);
+}
+
+#[test]
+fn overlong_header_lines_and_too_many_headers_are_refused() {
+    let server = Server::start("headers");
+    let refused = |head: String| {
+        let mut stream = TcpStream::connect(&server.addr).unwrap();
+        stream.write_all(head.as_bytes()).unwrap();
+        let mut raw = Vec::new();
+        // The server may hang up with some of the request unread.
+        let _ = stream.read_to_end(&mut raw);
+        Reply::parse(&raw).status
+    };
+    let long = format!("PUT /kv/a HTTP/1.1\r\nX-Long: {}\r\n\r\n", "x".repeat(20_000));
+    assert_eq!(refused(long), 431);
+    let many: String = (0..101).map(|i| format!("X-{}: y\r\n", i)).collect();
+    assert_eq!(refused(format!("PUT /kv/a HTTP/1.1\r\n{}\r\nbody", many)), 431);
+    let line = format!("PUT /kv/{} HTTP/1.1\r\n\r\n", "a".repeat(20_000));
+    assert_eq!(refused(line), 400);
+    assert_eq!(server.request("GET", "/kv/a", &[], b"").status, 404);
+}
+
+#[test]
+fn reserved_keys_are_refused() {
+    let server = Server::start("reserved");
+    assert_eq!(server.request("PUT", "/kv/%2Btxn", &[], b"x").status, 400);
+    assert_eq!(server.request("PUT", "/kv/after", &[], b"y").status, 201);
+    assert_eq!(server.request("GET", "/kv/after", &[], b"").body, b"y");
+}
+
+#[test]
+fn etags_guard_conditional_writes() {
+    let server = Server::start("etags");
+    let first = server.request("PUT", "/kv/counter", &[("If-None-Match", "*")], b"1");
+    assert_eq!(first.status, 201);
+    let etag = first.etag.unwrap();
+
+    let again = server.request("PUT", "/kv/counter", &[("If-None-Match", "*")], b"1");
+    assert_eq!(again.status, 412);
+
+    let second = server.request("PUT", "/kv/counter", &[("If-Match", &etag)], b"2");
+    assert_eq!(second.status, 200);
+    assert_ne!(second.etag.as_ref(), Some(&etag));
+
+    let stale = server.request("PUT", "/kv/counter", &[("If-Match", &etag)], b"3");
+    assert_eq!(stale.status, 412);
+    let stale = server.request("DELETE", "/kv/counter", &[("If-Match", &etag)], b"");
+    assert_eq!(stale.status, 412);
+    assert_eq!(server.request("GET", "/kv/counter", &[], b"").body, b"2");
+}
+
+#[test]
+fn lists_keys_by_prefix_and_reports_stats() {
+    let server = Server::start("prefix");
+    for key in ["user:1", "user:2", "group:1"] {
+        assert_eq!(server.request("PUT", &format!("/kv/{}", key), &[], b"x").status, 201);
+    }
+    server.request("DELETE", "/kv/user:2", &[], b"");
+
+    let listed = server.request("GET", "/kv?prefix=user", &[], b"");
+    assert_eq!(listed.status, 200);
+    assert_eq!(listed.body, br#"["user%3A1"]"#);
+
+    let stats = server.request("GET", "/stats", &[], b"");
+    assert_eq!(stats.status, 200);
+    let stats = String::from_utf8(stats.body).unwrap();
+    assert!(stats.contains(r#""live_keys":2"#), "{}", stats);
+    assert!(stats.contains(r#""tombstones":1"#), "{}", stats);
+    assert!(stats.contains(r#""bloom":{"num_bits""#), "{}", stats);
+
+    assert_eq!(server.request("POST", "/kv/user:1", &[], b"").status, 405);
+    assert_eq!(server.request("GET", "/nowhere", &[], b"").status, 404);
+}
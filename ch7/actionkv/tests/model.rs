@@ -0,0 +1,208 @@
+// Checks `ActionKV` against a `HashMap` model: random sequences of writes must
+// read back the same through the live handle and after reopening the file,
+// and a crash part way through the last write must lose that write and
+// nothing else.
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+use libactionkv::{ActionKV, ByteString};
+use proptest::prelude::*;
+
+type Model = HashMap<ByteString, ByteString>;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(ByteString, ByteString),
+    Update(ByteString, ByteString),
+    Delete(ByteString),
+}
+
+impl Op {
+    fn apply(&self, store: &mut ActionKV, model: &mut Model) {
+        match self {
+            Op::Insert(k, v) => {
+                store.insert(k, v).unwrap();
+                model.insert(k.clone(), v.clone());
+            }
+            Op::Update(k, v) => {
+                store.update(k, v).unwrap();
+                model.insert(k.clone(), v.clone());
+            }
+            // `delete` writes an empty value, which `get` hands back as is.
+            Op::Delete(k) => {
+                store.delete(k).unwrap();
+                model.insert(k.clone(), ByteString::new());
+            }
+        }
+    }
+}
+
+// A handful of short keys so that sequences revisit keys often.
+fn key() -> impl Strategy<Value = ByteString> {
+    prop::collection::vec(0u8..4, 1..3)
+}
+
+fn value() -> impl Strategy<Value = ByteString> {
+    prop::collection::vec(any::<u8>(), 1..64)
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (key(), value()).prop_map(|(k, v)| Op::Insert(k, v)),
+        (key(), value()).prop_map(|(k, v)| Op::Update(k, v)),
+        key().prop_map(Op::Delete),
+    ]
+}
+
+fn open(path: &Path) -> ActionKV {
+    let mut store = ActionKV::open(path).unwrap();
+    store.load().unwrap();
+    store
+}
+
+fn assert_matches(store: &mut ActionKV, model: &Model) {
+    assert_eq!(store.index.len(), model.len());
+    for (k, v) in model {
+        assert_eq!(store.get(k).unwrap().as_ref(), Some(v), "key {:?}", k);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn load_reproduces_the_model(ops in prop::collection::vec(op(), 1..40)) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("model.akv");
+        let mut model = Model::new();
+
+        let mut store = open(&path);
+        for op in &ops {
+            op.apply(&mut store, &mut model);
+        }
+        assert_matches(&mut store, &model);
+        drop(store);
+
+        assert_matches(&mut open(&path), &model);
+    }
+
+    #[test]
+    fn crash_during_last_write_loses_only_that_write(
+        ops in prop::collection::vec(op(), 1..20),
+        last in op(),
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crash.akv");
+        let mut model = Model::new();
+
+        let mut store = open(&path);
+        for op in &ops {
+            op.apply(&mut store, &mut model);
+        }
+        let before = fs::metadata(&path).unwrap().len();
+        let mut after_model = model.clone();
+        last.apply(&mut store, &mut after_model);
+        drop(store);
+        let after = fs::metadata(&path).unwrap().len();
+
+        let full = fs::read(&path).unwrap();
+        let crashed = dir.path().join("crashed.akv");
+        for cut in before..after {
+            fs::write(&crashed, &full[..cut as usize]).unwrap();
+            assert_matches(&mut open(&crashed), &model);
+        }
+        assert_matches(&mut open(&path), &after_model);
+    }
+}
+
+#[test]
+fn crash_during_transaction_loses_all_of_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("txn.akv");
+    let mut store = open(&path);
+    store.insert(b"a", b"1").unwrap();
+    let before = fs::metadata(&path).unwrap().len();
+
+    let mut txn = store.transaction();
+    txn.insert(b"a", b"2");
+    txn.insert(b"b", b"2");
+    txn.delete(b"c");
+    assert!(store.commit(txn).unwrap());
+    drop(store);
+    let after = fs::metadata(&path).unwrap().len();
+
+    for cut in (before..after).rev() {
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(cut).unwrap();
+        let mut crashed = open(&path);
+        assert_eq!(crashed.get(b"a").unwrap(), Some(b"1".to_vec()), "cut at {}", cut);
+        assert_eq!(crashed.get(b"b").unwrap(), None, "cut at {}", cut);
+    }
+}
+
+#[test]
+fn corrupt_record_is_an_error_not_a_panic() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("corrupt.akv");
+    let mut store = open(&path);
+    store.insert(b"key", b"value").unwrap();
+    drop(store);
+
+    let mut bytes = fs::read(&path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 0xff;
+    fs::write(&path, &bytes).unwrap();
+
+    let mut store = ActionKV::open(&path).unwrap();
+    let err = store.load().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn reserved_key_is_refused_and_takes_nothing_with_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reserved.akv");
+    let mut store = open(&path);
+    assert!(ActionKV::is_reserved_key(b"+txn"));
+    let err = store.insert(b"+txn", b"x").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    let mut txn = store.transaction();
+    txn.insert(b"a", b"1");
+    txn.insert(b"+txn", b"x");
+    assert_eq!(store.commit(txn).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+    store.insert(b"b", b"y").unwrap();
+    store.insert(b"c", b"z").unwrap();
+    drop(store);
+
+    let mut model = Model::new();
+    model.insert(b"b".to_vec(), b"y".to_vec());
+    model.insert(b"c".to_vec(), b"z".to_vec());
+    assert_matches(&mut open(&path), &model);
+    assert_eq!(ActionKV::open(&path).unwrap().check().unwrap().trailing, 0);
+}
+
+#[test]
+fn commit_sees_writes_from_another_handle_made_before_its_own() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("handles.akv");
+    let mut a = open(&path);
+    let mut b = open(&path);
+
+    let mut txn = a.transaction();
+    assert_eq!(txn.get(&mut a, b"k").unwrap(), None);
+    b.insert(b"k", b"from b").unwrap();
+    // Appending after `b` mustn't make `a` skip over what `b` wrote.
+    a.insert(b"other", b"x").unwrap();
+    txn.insert(b"k", b"from a");
+    assert!(!a.commit(txn).unwrap());
+
+    assert_eq!(a.get(b"k").unwrap(), Some(b"from b".to_vec()));
+    assert_eq!(a.get(b"other").unwrap(), Some(b"x".to_vec()));
+    let mut model = Model::new();
+    model.insert(b"k".to_vec(), b"from b".to_vec());
+    model.insert(b"other".to_vec(), b"x".to_vec());
+    assert_matches(&mut a, &model);
+    assert_matches(&mut open(&path), &model);
+}
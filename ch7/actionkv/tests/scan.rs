@@ -0,0 +1,67 @@
+use libactionkv::{ActionKV, MemStorage, ScanFilter};
+use regex::bytes::Regex;
+
+fn store_with(records: &[(&str, &str)]) -> ActionKV {
+    let mut store = ActionKV::with_storage(Box::new(MemStorage::new())).unwrap();
+    store.load().unwrap();
+    for (key, value) in records {
+        store.insert(key.as_bytes(), value.as_bytes()).unwrap();
+    }
+    store
+}
+
+fn keys(store: &mut ActionKV, filter: &ScanFilter) -> Vec<String> {
+    store
+        .scan(filter)
+        .map(|r| String::from_utf8(r.unwrap().1.key).unwrap())
+        .collect()
+}
+
+#[test]
+fn scan_returns_only_the_latest_live_records_in_log_order() {
+    let mut store = store_with(&[
+        ("apple", "red"),
+        ("banana", "yellow"),
+        ("cherry", "red"),
+        ("apple", "green"),
+    ]);
+    store.delete(b"cherry").unwrap();
+
+    let all: Vec<_> = store.scan(&ScanFilter::new()).map(Result::unwrap).collect();
+    assert_eq!(all.len(), 2);
+    assert_eq!(all[0].1.key, b"banana");
+    assert_eq!((&all[1].1.key[..], &all[1].1.value[..]), (&b"apple"[..], &b"green"[..]));
+    for (pos, kv) in all {
+        assert_eq!(store.get_at(pos).unwrap().key, kv.key);
+    }
+}
+
+#[test]
+fn filters_combine() {
+    let mut store = store_with(&[
+        ("user:1", r#"{"name":"ann"}"#),
+        ("user:2", r#"{"name":"bob"}"#),
+        ("group:1", r#"{"name":"ann's group"}"#),
+    ]);
+
+    let by_regex = ScanFilter::new().key_regex(Regex::new("^user:").unwrap());
+    assert_eq!(keys(&mut store, &by_regex), ["user:1", "user:2"]);
+
+    let by_value = ScanFilter::new().value_contains(b"ann");
+    assert_eq!(keys(&mut store, &by_value), ["user:1", "group:1"]);
+
+    let both = by_regex.value_contains(b"ann");
+    assert_eq!(keys(&mut store, &both), ["user:1"]);
+
+    let by_predicate = ScanFilter::new().key(|k| k.ends_with(b"2"));
+    assert_eq!(keys(&mut store, &by_predicate), ["user:2"]);
+}
+
+#[test]
+fn find_uses_the_index() {
+    let mut store = store_with(&[("a", "1"), ("b", "2"), ("a", "3")]);
+    let (pos, value) = store.find(b"a").unwrap().unwrap();
+    assert_eq!(value, b"3");
+    assert_eq!(store.index[&b"a".to_vec()], pos);
+    assert_eq!(store.find(b"z").unwrap(), None);
+}
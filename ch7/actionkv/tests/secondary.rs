@@ -0,0 +1,60 @@
+use serde_json::json;
+
+use libactionkv::{ActionKV, MemStorage};
+
+fn open(mem: &MemStorage) -> ActionKV {
+    let mut store = ActionKV::with_storage(Box::new(mem.clone())).unwrap();
+    store.load().unwrap();
+    store
+}
+
+#[test]
+fn find_by_follows_updates_and_deletes() {
+    let mut store = open(&MemStorage::new());
+    store.insert(b"ann", br#"{"city": "Oslo"}"#).unwrap();
+    store.insert(b"bob", br#"{"city": "Oslo"}"#).unwrap();
+    store.create_index("city", "/city").unwrap();
+    assert_eq!(store.find_by("city", &json!("Oslo")).unwrap(), [b"ann", b"bob"]);
+
+    store.update(b"ann", br#"{"city": "Rome"}"#).unwrap();
+    assert_eq!(store.find_by("city", &json!("Oslo")).unwrap(), [b"bob"]);
+    assert_eq!(store.find_by("city", &json!("Rome")).unwrap(), [b"ann"]);
+
+    store.delete(b"bob").unwrap();
+    assert!(store.find_by("city", &json!("Oslo")).unwrap().is_empty());
+    store.update(b"ann", b"not json").unwrap();
+    assert!(store.find_by("city", &json!("Rome")).unwrap().is_empty());
+}
+
+#[test]
+fn numbers_match_by_value() {
+    let mut store = open(&MemStorage::new());
+    store.insert(b"a", br#"{"n": 1.0}"#).unwrap();
+    store.insert(b"b", br#"{"n": 1}"#).unwrap();
+    store.insert(b"c", br#"{"n": "1"}"#).unwrap();
+    store.create_index("n", "/n").unwrap();
+    assert_eq!(store.find_by("n", &json!(1)).unwrap(), [b"a", b"b"]);
+    assert_eq!(store.find_by("n", &json!(1.0)).unwrap(), [b"a", b"b"]);
+    assert_eq!(store.find_by("n", &json!("1")).unwrap(), [b"c"]);
+}
+
+#[test]
+fn definitions_are_kept_in_the_log() {
+    let mem = MemStorage::new();
+    let mut store = open(&mem);
+    store.insert(b"ann", br#"{"age": 30}"#).unwrap();
+    store.create_index("age", "/age").unwrap();
+    store.create_index("gone", "/gone").unwrap();
+    store.drop_index("gone").unwrap();
+    store.insert(b"bob", br#"{"age": 30}"#).unwrap();
+    drop(store);
+
+    let mut store = open(&mem);
+    assert_eq!(store.secondary_index("age").unwrap().pointer(), "/age");
+    assert!(store.secondary_index("gone").is_none());
+    assert_eq!(store.find_by("age", &json!(30)).unwrap(), [b"ann", b"bob"]);
+    // The definitions are the store's own business.
+    assert!(!store.index.keys().any(|k| ActionKV::is_reserved_key(k)));
+    assert_eq!(store.stats().unwrap().live_keys, 2);
+    assert!(store.insert(b"+indexes", b"{}").is_err());
+}
@@ -0,0 +1,139 @@
+use std::io;
+
+use libactionkv::{ActionKV, Fault, FaultyStorage, MemStorage};
+
+fn reopen(mem: &MemStorage) -> ActionKV {
+    let mut store = ActionKV::with_storage(Box::new(mem.clone())).unwrap();
+    store.load().unwrap();
+    store
+}
+
+#[test]
+fn memory_storage_survives_reopening() {
+    let mem = MemStorage::new();
+    let mut store = reopen(&mem);
+    store.insert(b"a", b"1").unwrap();
+    store.update(b"a", b"2").unwrap();
+    store.insert(b"b", b"3").unwrap();
+    drop(store);
+
+    let mut store = reopen(&mem);
+    assert_eq!(store.get(b"a").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(store.get(b"b").unwrap(), Some(b"3".to_vec()));
+}
+
+#[test]
+fn failed_append_leaves_the_store_unchanged() {
+    let mem = MemStorage::new();
+    let faulty = FaultyStorage::new(mem.clone());
+    let mut store = ActionKV::with_storage(Box::new(faulty.clone())).unwrap();
+    store.load().unwrap();
+    store.insert(b"a", b"1").unwrap();
+
+    faulty.fail_next_append(Fault::Error(io::ErrorKind::Other));
+    assert!(store.insert(b"a", b"2").is_err());
+    assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(reopen(&mem).get(b"a").unwrap(), Some(b"1".to_vec()));
+}
+
+#[test]
+fn short_write_is_rolled_back_so_later_writes_are_readable() {
+    let mem = MemStorage::new();
+    let faulty = FaultyStorage::new(mem.clone());
+    let mut store = ActionKV::with_storage(Box::new(faulty.clone())).unwrap();
+    store.load().unwrap();
+    store.insert(b"a", b"1").unwrap();
+    let len = mem.to_bytes().len();
+
+    faulty.fail_next_append(Fault::ShortWrite(5));
+    let err = store.insert(b"b", b"2").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WriteZero);
+    assert_eq!(mem.to_bytes().len(), len);
+
+    store.insert(b"c", b"3").unwrap();
+    let mut store = reopen(&mem);
+    assert_eq!(store.get(b"b").unwrap(), None);
+    assert_eq!(store.get(b"c").unwrap(), Some(b"3".to_vec()));
+}
+
+#[test]
+fn short_write_that_cannot_be_rolled_back_is_lost_on_load() {
+    let mem = MemStorage::new();
+    let faulty = FaultyStorage::new(mem.clone());
+    let mut store = ActionKV::with_storage(Box::new(faulty.clone())).unwrap();
+    store.load().unwrap();
+    store.insert(b"a", b"1").unwrap();
+
+    faulty.fail_next_append(Fault::ShortWrite(5));
+    faulty.fail_next_truncate(io::ErrorKind::Other);
+    assert!(store.insert(b"b", b"2").is_err());
+    let torn = mem.to_bytes().len();
+
+    // Nothing is written behind the torn record: the rollback is tried
+    // again first, and the write is refused while that keeps failing.
+    faulty.fail_next_truncate(io::ErrorKind::Other);
+    assert!(store.insert(b"c", b"3").is_err());
+    assert_eq!(mem.to_bytes().len(), torn);
+    store.insert(b"d", b"4").unwrap();
+
+    let mut store = reopen(&mem);
+    assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(store.get(b"b").unwrap(), None);
+    assert_eq!(store.get(b"c").unwrap(), None);
+    assert_eq!(store.get(b"d").unwrap(), Some(b"4".to_vec()));
+}
+
+#[test]
+fn torn_record_left_by_a_crash_is_cut_off_by_the_next_write() {
+    let mem = MemStorage::new();
+    reopen(&mem).insert(b"a", b"1").unwrap();
+    let mut bytes = mem.to_bytes();
+    bytes.extend_from_slice(&[1, 2, 3, 4, 5]);
+    let mem = MemStorage::from_bytes(bytes);
+
+    reopen(&mem).insert(b"b", b"2").unwrap();
+    let mut store = reopen(&mem);
+    assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+    assert_eq!(store.get(b"b").unwrap(), Some(b"2".to_vec()));
+    assert_eq!(store.check().unwrap().trailing, 0);
+}
+
+#[test]
+fn torn_record_is_not_cut_off_over_records_another_handle_wrote() {
+    let mem = MemStorage::new();
+    reopen(&mem).insert(b"a", b"1").unwrap();
+    let mut bytes = mem.to_bytes();
+    bytes.extend_from_slice(&[1, 2, 3]);
+    let mem = MemStorage::from_bytes(bytes);
+
+    // Both handles find the same torn record, but only the first to write
+    // may cut it off.
+    let mut a = reopen(&mem);
+    let mut b = reopen(&mem);
+    b.insert(b"b", b"2").unwrap();
+    b.insert(b"c", b"3").unwrap();
+    a.insert(b"d", b"4").unwrap();
+
+    let mut store = reopen(&mem);
+    for (key, value) in [(b"a", b"1"), (b"b", b"2"), (b"c", b"3"), (b"d", b"4")] {
+        assert_eq!(store.get(key).unwrap(), Some(value.to_vec()));
+    }
+    assert_eq!(store.check().unwrap().trailing, 0);
+}
+
+#[test]
+fn read_and_sync_errors_are_reported() {
+    let faulty = FaultyStorage::new(MemStorage::new());
+    let mut store = ActionKV::with_storage(Box::new(faulty.clone())).unwrap();
+    store.load().unwrap();
+    store.insert(b"a", b"1").unwrap();
+
+    faulty.fail_next_read(io::ErrorKind::PermissionDenied);
+    let err = store.get(b"a").unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    assert_eq!(store.get(b"a").unwrap(), Some(b"1".to_vec()));
+
+    faulty.fail_next_sync(io::ErrorKind::Other);
+    assert!(store.sync().is_err());
+    assert!(store.sync().is_ok());
+}
@@ -0,0 +1,111 @@
+use std::fs::{self, File};
+use std::io::{self, prelude::*, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ignore::WalkBuilder;
+use regex::Regex;
+
+// Runs the built binary, as that is what users see, including the cost of
+// walking the tree and writing the output.
+fn grep_lite(dir: &Path, args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_grep-lite"))
+        .current_dir(dir)
+        .args(args)
+        .stdout(Stdio::null())
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+// Something shaped like a source tree: many smallish files across a few
+// levels of directories, with an occasional match.
+fn tree(files: usize, lines: usize) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..files {
+        let path = dir.path().join(format!("m{}/p{}/f{}.rs", i % 7, i % 31, i));
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let body: String = (0..lines)
+            .map(|j| match j % 97 {
+                0 => format!("fn needle_{}() -> usize {{ {} }}\n", j, i),
+                _ => format!("    let value_{} = compute({}, {});\n", j, i, j),
+            })
+            .collect();
+        fs::write(path, body).unwrap();
+    }
+    dir
+}
+
+// How grep-lite searched a tree before it had a worker pool: one file after
+// another, each read with `reader.lines()` and printed as it goes. It runs
+// in-process, so unlike the others it doesn't pay for starting the binary.
+fn lines_loop(dir: &Path, pattern: &str) {
+    let re = Regex::new(pattern).unwrap();
+    let mut out = io::sink();
+    for entry in WalkBuilder::new(dir).build() {
+        let entry = entry.unwrap();
+        if !entry.file_type().is_some_and(|t| t.is_file()) {
+            continue;
+        }
+        let reader = BufReader::new(File::open(entry.path()).unwrap());
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.unwrap();
+            if re.is_match(&line) {
+                writeln!(out, "{}:{}:{}", entry.path().display(), i + 1, line).unwrap();
+            }
+        }
+    }
+}
+
+fn bench_threads(c: &mut Criterion) {
+    let dir = tree(400, 2_000);
+    let mut group = c.benchmark_group("tree");
+    group.sample_size(10);
+    group.bench_function("lines-loop", |b| b.iter(|| lines_loop(dir.path(), "needle_[0-9]+")));
+    group.bench_function("single-threaded", |b| {
+        b.iter(|| grep_lite(dir.path(), &["needle_[0-9]+", ".", "-j", "1"]))
+    });
+    group.bench_function("parallel", |b| {
+        b.iter(|| grep_lite(dir.path(), &["needle_[0-9]+", "."]))
+    });
+    group.bench_function("parallel-sorted", |b| {
+        b.iter(|| grep_lite(dir.path(), &["needle_[0-9]+", ".", "--sort", "path"]))
+    });
+    group.finish();
+}
+
+// One big file with a match every few thousand lines, where how the file
+// is read and scanned matters more than anything else.
+fn big_file(lines: usize) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let body: String = (0..lines)
+        .map(|i| match i % 5_000 {
+            0 => format!("fn needle_{}() {{}}\n", i),
+            _ => format!("    let value_{} = compute({}, {});\n", i, i * 7, i % 97),
+        })
+        .collect();
+    fs::write(dir.path().join("big.rs"), body).unwrap();
+    dir
+}
+
+fn bench_mmap(c: &mut Criterion) {
+    let dir = big_file(1_000_000);
+    let mut group = c.benchmark_group("big-file");
+    group.sample_size(10);
+    for (name, args) in [
+        ("literal-read", &["-n", "needle", "--no-mmap"][..]),
+        ("literal-mmap", &["-n", "needle", "--mmap"]),
+        ("literals-read", &["-n", "-e", "needle", "-e", "haystack", "--no-mmap"]),
+        ("literals-mmap", &["-n", "-e", "needle", "-e", "haystack", "--mmap"]),
+        ("regex-mmap", &["-n", "needle_[0-9]+", "--mmap"]),
+    ] {
+        let mut args = args.to_vec();
+        args.push("big.rs");
+        group.bench_function(name, |b| b.iter(|| grep_lite(dir.path(), &args)));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_threads, bench_mmap);
+criterion_main!(benches);
@@ -0,0 +1,71 @@
+use std::io::{self, prelude::*};
+use std::path::Path;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::bufread::MultiGzDecoder;
+use xz2::bufread::XzDecoder;
+
+/// Compressed formats that -z can look inside.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Xz,
+    Bzip2,
+}
+
+// How each format's data starts. bzip2 is left to `is_bzip2`.
+const COMPRESSION_MAGIC: [(&[u8], Compression); 3] = [
+    (b"\x1f\x8b", Compression::Gzip),
+    (b"\x28\xb5\x2f\xfd", Compression::Zstd),
+    (b"\xfd7zXZ\x00", Compression::Xz),
+];
+
+// The magic of a bzip2 block, and of the end of a stream, which comes
+// straight after the header when a stream is empty.
+const BZIP2_BLOCK_MAGIC: &[u8] = b"1AY&SY";
+const BZIP2_END_MAGIC: &[u8] = b"\x17\x72\x45\x38\x50\x90";
+
+impl Compression {
+    /// Goes by the first bytes of the data, `head`, and failing that the
+    /// file's extension.
+    pub fn detect(path: Option<&Path>, head: &[u8]) -> Option<Compression> {
+        let by_magic = COMPRESSION_MAGIC
+            .iter()
+            .find(|(magic, _)| head.starts_with(magic))
+            .map(|&(_, compression)| compression)
+            .or_else(|| is_bzip2(head).then_some(Compression::Bzip2));
+        let ext = path.and_then(Path::extension).and_then(|ext| ext.to_str());
+        by_magic.or(match ext {
+            Some("gz" | "tgz") => Some(Compression::Gzip),
+            Some("zst" | "zstd") => Some(Compression::Zstd),
+            Some("xz" | "txz") => Some(Compression::Xz),
+            Some("bz2" | "tbz2") => Some(Compression::Bzip2),
+            _ => None,
+        })
+    }
+
+    /// Wraps `reader` in a decoder for this format.
+    pub fn decoder<'a, R: BufRead + 'a>(self, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            // Rotated logs are sometimes several compressed streams one after
+            // another, so all of them are read rather than just the first.
+            Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::Decoder::with_buffer(reader)?),
+            Compression::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+            Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+        })
+    }
+}
+
+// bzip2's own magic, "BZh", is common enough at the start of plain text that
+// the block size digit after it and the magic that follows that are checked
+// too.
+fn is_bzip2(head: &[u8]) -> bool {
+    match head {
+        [b'B', b'Z', b'h', b'1'..=b'9', rest @ ..] => {
+            rest.starts_with(BZIP2_BLOCK_MAGIC) || rest.starts_with(BZIP2_END_MAGIC)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,80 @@
+use std::fs::{self, File};
+use std::io::{self, prelude::*};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+// How often --follow checks a file that has nothing more to read.
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Reads a file like `tail -f`: at the end of it, waits for more to be
+/// written rather than stopping. A file that gets shorter is read again from
+/// the start, and when another file takes its name, as when a log is
+/// rotated, that one is read from the start instead. Either way, line numbers
+/// carry on from where they'd got to.
+pub struct Follow<'a> {
+    path: &'a Path,
+    name: &'a str,
+    file: File,
+    // How far into `file` has been read.
+    pos: u64,
+}
+
+impl<'a> Follow<'a> {
+    /// Opens `path`, calling it `name` in messages about it.
+    pub fn open(path: &'a Path, name: &'a str) -> io::Result<Follow<'a>> {
+        Ok(Follow { path, name, file: File::open(path)?, pos: 0 })
+    }
+
+    // Whether `path` now names a different file from the one being read.
+    // It may name nothing at all for a moment while a log is rotated.
+    fn replaced(&self) -> io::Result<bool> {
+        match fs::metadata(self.path) {
+            Ok(meta) => Ok(!same_file(&meta, &self.file.metadata()?)),
+            Err(_) => Ok(false),
+        }
+    }
+}
+
+impl Read for Follow<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.file.read(buf)?;
+            if n > 0 {
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            if self.file.metadata()?.len() < self.pos {
+                eprintln!("grep-lite: {}: file truncated", self.name);
+                self.file.seek(io::SeekFrom::Start(0))?;
+                self.pos = 0;
+            } else if self.replaced()? {
+                // Anything written to the old file before it was replaced is
+                // read first.
+                let n = self.file.read(buf)?;
+                if n > 0 {
+                    self.pos += n as u64;
+                    return Ok(n);
+                }
+                eprintln!("grep-lite: {}: file replaced, following the new one", self.name);
+                self.file = File::open(self.path)?;
+                self.pos = 0;
+            } else {
+                thread::sleep(FOLLOW_INTERVAL);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+// Without inode numbers to go by, a file made in place of another can only
+// be told apart by when it was made.
+#[cfg(not(unix))]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    a.created().ok() == b.created().ok()
+}
@@ -0,0 +1,13 @@
+mod compression;
+mod follow;
+mod matcher;
+mod printer;
+mod searcher;
+
+pub use compression::Compression;
+pub use follow::Follow;
+pub use matcher::{MatchOpts, Matcher, Replacement};
+pub use printer::{Colors, JsonSink, PrintOpts, StandardSink, MATCH_DELIM};
+pub use searcher::{
+    binary_offset, binary_offset_head, Line, BINARY_CHECK_LEN, SearchStats, Searcher, Sink,
+};
@@ -1,77 +1,142 @@
-use std::fs::File;
+use std::ffi::OsString;
+use std::fs::{self, File};
 use std::io::{prelude::*, BufReader, self};
-use std::collections::vec_deque::VecDeque;
-use regex::Regex;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Display;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
 use clap::{App, Arg, ArgMatches};
+use encoding_rs::Encoding;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+use memmap2::Mmap;
+use ignore::overrides::OverrideBuilder;
+use ignore::types::{Types, TypesBuilder};
+use ignore::WalkBuilder;
+use grep_lite::{
+    binary_offset, binary_offset_head, Colors, Compression, Follow, JsonSink, Line, MatchOpts,
+    Matcher, PrintOpts, Replacement, SearchStats, Searcher, Sink, StandardSink, BINARY_CHECK_LEN,
+    MATCH_DELIM,
+};
 
-const EMPTY_STR: &str = "";
-const MATCH_DELIM: &str = ":";
-const CTX_DELIM: &str = "-";
 const STDIN_INPUT: &str = "-";
+const STDIN_LABEL: &str = "(standard input)";
 const ARG_PATTERN: &str = "PATTERN";
 const ARG_FILE: &str = "FILE";
 const ARG_LINUM_ON: &str = "LINUM_ON";
 const ARG_CTX: &str = "CTX_ON";
+const ARG_BEFORE: &str = "BEFORE";
+const ARG_AFTER: &str = "AFTER";
+const ARG_GROUP_SEPARATOR: &str = "GROUP_SEPARATOR";
+const ARG_NO_GROUP_SEPARATOR: &str = "NO_GROUP_SEPARATOR";
+const DEFAULT_GROUP_SEPARATOR: &str = "--";
 const ARG_RE_MODE: &str = "RE_MODE";
+const ARG_FIXED: &str = "FIXED";
+const ARG_REGEXP: &str = "REGEXP";
+const ARG_PATTERN_FILE: &str = "PATTERN_FILE";
+const ARG_IGNORE_CASE: &str = "IGNORE_CASE";
+const ARG_INVERT: &str = "INVERT";
+const ARG_WORD: &str = "WORD";
+const ARG_LINE: &str = "LINE";
+const ARG_GLOB: &str = "GLOB";
+const ARG_TYPE: &str = "TYPE";
+const ARG_HIDDEN: &str = "HIDDEN";
+const ARG_NO_IGNORE: &str = "NO_IGNORE";
+const ARG_THREADS: &str = "THREADS";
+const ARG_SORT: &str = "SORT";
+const ARG_COUNT: &str = "COUNT";
+const ARG_FILES_WITH_MATCHES: &str = "FILES_WITH_MATCHES";
+const ARG_FILES_WITHOUT_MATCH: &str = "FILES_WITHOUT_MATCH";
+const ARG_QUIET: &str = "QUIET";
+const ARG_MAX_COUNT: &str = "MAX_COUNT";
+const ARG_COLOR: &str = "COLOR";
+const ARG_ONLY_MATCHING: &str = "ONLY_MATCHING";
+const ARG_JSON: &str = "JSON";
+const ARG_TEXT: &str = "TEXT";
+const ARG_ENCODING: &str = "ENCODING";
+const ARG_REPLACE: &str = "REPLACE";
+const ARG_IN_PLACE: &str = "IN_PLACE";
+const ARG_MULTILINE: &str = "MULTILINE";
+const ARG_MULTILINE_DOTALL: &str = "MULTILINE_DOTALL";
+const ARG_SEARCH_ZIP: &str = "SEARCH_ZIP";
+const ARG_MMAP: &str = "MMAP";
+const ARG_NO_MMAP: &str = "NO_MMAP";
+const ARG_FOLLOW: &str = "FOLLOW";
 
-struct Ctx {
-    data: VecDeque<String>,
-    capacity: usize,
-}
+// Exit statuses, as for grep.
+const EXIT_MATCH: i32 = 0;
+const EXIT_NO_MATCH: i32 = 1;
+const EXIT_ERROR: i32 = 2;
 
-impl Ctx {
-    pub fn with_capacity(capacity: usize) -> Ctx {
-        Ctx {
-            data: VecDeque::with_capacity(capacity),
-            capacity
-        }
-    }
+// Set once anything has gone wrong, so that the exit status can say so even
+// though the search carries on.
+static HAD_ERROR: AtomicBool = AtomicBool::new(false);
 
-    pub fn len(&self) -> usize {
-        self.data.len()
-    }
+// The longest byte order mark, UTF-8's.
+const BOM_MAX_LEN: usize = 3;
+const BOMS: [&[u8]; 3] = [b"\xef\xbb\xbf", b"\xff\xfe", b"\xfe\xff"];
 
-    pub fn push_back(&mut self, item: &str) {
-        if self.len() == self.capacity {
-            self.data.pop_front();
-        }
-        self.data.push_back(String::from(item));
-    }
-    
-    pub fn get(&self, i: usize) -> Option<&str> {
-        self.data.get(i).map(|x| &**x)
-    }
-}
+// Files at least this big are memory-mapped unless --no-mmap is given.
+// Mapping costs more than reading a small file would.
+const MMAP_MIN_LEN: u64 = 1024 * 1024;
 
-struct Matcher<'a> {
-    term: &'a str,
-    re: Option<Regex>,
+// Something to search, in the order it was found.
+enum Input {
+    Stdin,
+    // `walked` is set for files found inside a directory, as opposed to
+    // being named on the command line.
+    File { path: PathBuf, walked: bool },
 }
 
-impl<'a> Matcher<'a> {
-    pub fn new(term: &'a str, re_mode: bool) -> Matcher<'a> {
-        let re = if re_mode {
-            Some(Matcher::parse_re(term))
-        } else { 
-            None
-        };
-        Matcher { term, re }
-    }
+// What is printed for each file.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Lines,
+    // Lines as JSON events, for other programs to read.
+    Json,
+    Count,
+    FilesWithMatches,
+    FilesWithoutMatch,
+    // Nothing at all; only the exit status matters.
+    Quiet,
+}
 
-    pub fn matches(&self, s: &str) -> bool {
-        if let Some(re) = &self.re {
-            re.find(s).is_some()
-        } else {
-            s.contains(&self.term)
-        }
-    }
+// Output settings shared by every file searched.
+struct Opts {
+    mode: Mode,
+    print: PrintOpts,
+    // Lines of context to print before and after each match.
+    before: usize,
+    after: usize,
+    // Stop reading a file after this many matching lines.
+    max_count: Option<u64>,
+    // Search files with NUL bytes as if they were text.
+    text: bool,
+    // What input is decoded from. `None` means UTF-8, or UTF-16 for input
+    // that starts with its byte order mark.
+    encoding: Option<&'static Encoding>,
+    // Write the replacements back to the files instead, keeping the
+    // originals with `backup_suffix` added to their names if it's set.
+    in_place: bool,
+    backup_suffix: Option<String>,
+    // Decompress compressed input before searching it.
+    search_zip: bool,
+    // Whether to memory-map files rather than read them; `None` leaves it to
+    // the size of the file.
+    mmap: Option<bool>,
+}
 
-    fn parse_re(term: &str) -> Regex {
-        Regex::new(term)
-            .expect(&format!(
-                    "Could not create a regular expression from {}",
-                    term))
-    }
+// Which files a directory is expanded into.
+struct WalkOpts {
+    globs: Vec<String>,
+    types: Types,
+    hidden: bool,
+    no_ignore: bool,
+    sorted: bool,
 }
 
 // cargo run -- args
@@ -79,119 +144,930 @@ impl<'a> Matcher<'a> {
 // cargo run --oo test.txt
 // or
 // cat test.txt | cargo run -- oo -
+// or, searching every file under src/
+// cargo run -- oo src
 fn main() {
     let args = parse_args();
-    let re_mode: bool = args.value_of_t(ARG_RE_MODE).unwrap_or(true);
-    let linum_on: bool = args.value_of_t(ARG_LINUM_ON).unwrap_or(false);
-    let ctx_lines: usize = args.value_of_t(ARG_CTX).unwrap_or(0);
-    let need_ctx = ctx_lines > 0;
-    let mut ctx: Ctx = if need_ctx {
-        Ctx::with_capacity(ctx_lines) 
+    let match_opts = MatchOpts {
+        // -F and -G override each other, so whichever came last wins.
+        fixed: args.is_present(ARG_FIXED),
+        ignore_case: args.is_present(ARG_IGNORE_CASE),
+        word: args.is_present(ARG_WORD),
+        line: args.is_present(ARG_LINE),
+        invert: args.is_present(ARG_INVERT),
+        multiline: args.is_present(ARG_MULTILINE),
+        dotall: args.is_present(ARG_MULTILINE_DOTALL),
+    };
+    let mut search_terms: Vec<String> = args
+        .values_of(ARG_REGEXP)
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect();
+    for path in args.values_of(ARG_PATTERN_FILE).into_iter().flatten() {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => search_terms.extend(contents.lines().map(String::from)),
+            Err(err) => fail(format!("{}: {}", path, err)),
+        }
+    }
+    let mut inputs: Vec<&str> = args.values_of(ARG_FILE).into_iter().flatten().collect();
+    // With -e or -f the patterns come from there, and the first positional
+    // argument is really a file.
+    let patterns_given = args.is_present(ARG_REGEXP) || args.is_present(ARG_PATTERN_FILE);
+    if let Some(term) = args.value_of(ARG_PATTERN) {
+        if patterns_given {
+            inputs.insert(0, term);
+        } else {
+            search_terms.push(term.to_string());
+        }
+    }
+    if inputs.is_empty() {
+        inputs.push(STDIN_INPUT);
+    }
+    let matcher = Matcher::new(&search_terms, &match_opts).unwrap_or_else(|err| fail(err));
+    let mode = if args.is_present(ARG_QUIET) {
+        Mode::Quiet
+    } else if args.is_present(ARG_FILES_WITH_MATCHES) {
+        Mode::FilesWithMatches
+    } else if args.is_present(ARG_FILES_WITHOUT_MATCH) {
+        Mode::FilesWithoutMatch
+    } else if args.is_present(ARG_COUNT) {
+        Mode::Count
+    } else if args.is_present(ARG_JSON) {
+        Mode::Json
     } else {
-        Ctx::with_capacity(0)
+        Mode::Lines
     };
-    let search_term = args.value_of(ARG_PATTERN).unwrap();
-    let stdin = &io::stdin();
-    let reader = new_reader(&args, stdin);
-    let matcher = Matcher::new(search_term, re_mode);
-    let mut ctx_head_offset: usize = 0; 
-    let mut rem_ctx_lines = 0;
-    for (i, line_) in reader.lines().enumerate() {
-        let line = &line_.unwrap();
-        let is_match;
-        if matcher.matches(&line) {
-            is_match = true;
-            rem_ctx_lines = ctx_lines;
+    let opts = Opts {
+        mode,
+        print: PrintOpts {
+            line_number: args.is_present(ARG_LINUM_ON),
+            group_separator: if args.is_present(ARG_NO_GROUP_SEPARATOR) {
+                None
+            } else {
+                let sep = args.value_of(ARG_GROUP_SEPARATOR).unwrap_or(DEFAULT_GROUP_SEPARATOR);
+                Some(sep.to_string())
+            },
+            // As with grep, names are only worth printing if there could be
+            // more than one.
+            with_filename: inputs.len() > 1 || inputs.iter().any(|i| Path::new(i).is_dir()),
+            only_matching: args.is_present(ARG_ONLY_MATCHING),
+            colors: match args.value_of(ARG_COLOR).unwrap_or("auto") {
+                _ if mode == Mode::Json => Colors::none(),
+                "always" => Colors::parse(&env::var("GREP_COLORS").unwrap_or_default()),
+                "auto"
+                    if io::stdout().is_terminal() && env::var("TERM").as_deref() != Ok("dumb") =>
+                {
+                    Colors::parse(&env::var("GREP_COLORS").unwrap_or_default())
+                }
+                _ => Colors::none(),
+            },
+            replace: args
+                .value_of(ARG_REPLACE)
+                .map(|template| Replacement::new(template, &matcher)),
+        },
+        // -A and -B take precedence over -C, whatever the order.
+        before: number_arg(&args, ARG_BEFORE)
+            .or_else(|| number_arg(&args, ARG_CTX))
+            .unwrap_or(0),
+        after: number_arg(&args, ARG_AFTER)
+            .or_else(|| number_arg(&args, ARG_CTX))
+            .unwrap_or(0),
+        max_count: number_arg(&args, ARG_MAX_COUNT),
+        text: args.is_present(ARG_TEXT),
+        encoding: args.value_of(ARG_ENCODING).map(|label| {
+            Encoding::for_label(label.as_bytes())
+                .unwrap_or_else(|| fail(format!("unknown encoding: {}", label)))
+        }),
+        in_place: args.is_present(ARG_IN_PLACE),
+        backup_suffix: args.value_of(ARG_IN_PLACE).map(String::from),
+        search_zip: args.is_present(ARG_SEARCH_ZIP),
+        mmap: if args.is_present(ARG_NO_MMAP) {
+            Some(false)
+        } else if args.is_present(ARG_MMAP) {
+            Some(true)
         } else {
-            is_match = false;
-        }
-        if is_match || rem_ctx_lines > 0 {
-            if need_ctx && is_match {
-                while ctx_head_offset != ctx.len() {
-                    print(ctx.get(ctx_head_offset).unwrap(),
-                        i - ctx_head_offset, false, linum_on);
-                    ctx_head_offset += 1;
+            None
+        },
+    };
+
+    // Only matching lines are printed by -l, -L and -q, so the first one is
+    // enough to know what to print for a file. Nor is there any context or
+    // binary detection unless lines are printed.
+    let searcher = match mode {
+        Mode::Lines | Mode::Json => Searcher::new()
+            .before_context(opts.before)
+            .after_context(opts.after)
+            .max_count(opts.max_count)
+            .binary_detection(!opts.text),
+        Mode::Count => Searcher::new().max_count(opts.max_count),
+        _ => Searcher::new().max_count(Some(1)),
+    };
+    if args.is_present(ARG_FOLLOW) {
+        follow(&inputs, &searcher, &matcher, &opts);
+    }
+
+    let threads = number_arg(&args, ARG_THREADS)
+        .unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    let walk_opts = walk_opts(&args);
+
+    // One thread walks the inputs, `threads` workers each search a whole file
+    // into a buffer, and this thread prints the buffers so that every file's
+    // output stays together.
+    let (input_tx, input_rx) = mpsc::channel::<(usize, Input)>();
+    let input_rx = Mutex::new(input_rx);
+    let (output_tx, output_rx) = mpsc::channel::<(usize, Vec<u8>, u64)>();
+    let mut matched = false;
+    thread::scope(|s| {
+        for _ in 0..threads.max(1) {
+            let input_rx = &input_rx;
+            let output_tx = output_tx.clone();
+            let (searcher, matcher, opts) = (&searcher, &matcher, &opts);
+            s.spawn(move || loop {
+                let next = input_rx.lock().unwrap().recv();
+                let (i, input) = match next {
+                    Ok(next) => next,
+                    Err(_) => break,
+                };
+                let mut buf = Vec::new();
+                let count = search_input(&input, &mut buf, searcher, matcher, opts);
+                if output_tx.send((i, buf, count)).is_err() {
+                    break;
                 }
+            });
+        }
+        drop(output_tx);
+
+        let (walk_opts, inputs) = (&walk_opts, &inputs);
+        s.spawn(move || {
+            let mut found = inputs.iter().flat_map(|input| walk(walk_opts, input));
+            for next in found.by_ref().enumerate() {
+                if input_tx.send(next).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        // With --sort, output that arrives early waits here for its turn.
+        let mut pending = BTreeMap::new();
+        let mut next = 0;
+        // Groups from different files are separated like those within one.
+        let separator = match &opts.print.group_separator {
+            Some(sep) if opts.mode == Mode::Lines && opts.before + opts.after > 0 => {
+                Some(format!("{}\n", opts.print.colors.paint(&opts.print.colors.separator, sep)))
+            }
+            _ => None,
+        };
+        let mut printed = false;
+        let mut write = |buf: &[u8]| -> io::Result<()> {
+            if buf.is_empty() {
+                return Ok(());
             }
-            print(line, i + 1, is_match, linum_on);
-            if !is_match && need_ctx {
-                rem_ctx_lines -= 1;
+            if let (Some(sep), true) = (&separator, printed) {
+                out.write_all(sep.as_bytes())?;
+            }
+            printed = true;
+            out.write_all(buf)
+        };
+        for (i, buf, count) in output_rx {
+            matched |= count > 0;
+            // One match settles the exit status, so there's no need to wait
+            // for the other files.
+            if matched && opts.mode == Mode::Quiet {
+                process::exit(EXIT_MATCH);
+            }
+            let written = if walk_opts.sorted {
+                pending.insert(i, buf);
+                let mut written = Ok(());
+                while let Some(buf) = pending.remove(&next) {
+                    written = written.and_then(|_| write(&buf));
+                    next += 1;
+                }
+                written
+            } else {
+                write(&buf)
+            };
+            // The reader has gone away, e.g. the output was piped to `head`.
+            if written.is_err() {
+                break;
             }
-        } else if need_ctx {
-            ctx.push_back(line);
-            ctx_head_offset = ctx_head_offset.saturating_sub(1);
         }
+    });
+
+    process::exit(if HAD_ERROR.load(Ordering::Relaxed) {
+        EXIT_ERROR
+    } else if matched {
+        EXIT_MATCH
+    } else {
+        EXIT_NO_MATCH
+    });
+}
+
+// Reports a problem with one input, and carries on with the rest.
+fn report<T: Display>(err: T) {
+    eprintln!("grep-lite: {}", err);
+    HAD_ERROR.store(true, Ordering::Relaxed);
+}
+
+// Reports a problem that stops the whole search.
+fn fail<T: Display>(err: T) -> ! {
+    eprintln!("grep-lite: {}", err);
+    process::exit(EXIT_ERROR);
+}
+
+fn number_arg<T: std::str::FromStr>(args: &ArgMatches, name: &str) -> Option<T> {
+    let value = args.value_of(name)?;
+    match value.parse() {
+        Ok(n) => Some(n),
+        Err(_) => fail(format!("invalid number: {}", value)),
+    }
+}
+
+fn walk_opts(args: &ArgMatches) -> WalkOpts {
+    let globs: Vec<String> = args
+        .values_of(ARG_GLOB)
+        .into_iter()
+        .flatten()
+        .map(String::from)
+        .collect();
+    // Check the globs now, rather than once for every input.
+    let mut overrides = OverrideBuilder::new(".");
+    for glob in &globs {
+        if let Err(err) = overrides.add(glob) {
+            fail(err);
+        }
+    }
+    let mut types = TypesBuilder::new();
+    types.add_defaults();
+    for name in args.values_of(ARG_TYPE).into_iter().flatten() {
+        types.select(name);
+    }
+    WalkOpts {
+        globs,
+        types: types.build().unwrap_or_else(|err| fail(err)),
+        hidden: args.is_present(ARG_HIDDEN),
+        no_ignore: args.is_present(ARG_NO_IGNORE),
+        sorted: args.value_of(ARG_SORT) == Some("path"),
+    }
+}
+
+// Expands `input` into the files to search, reporting any it can't read.
+fn walk<'a>(opts: &'a WalkOpts, input: &'a str) -> Box<dyn Iterator<Item = Input> + 'a> {
+    if input == STDIN_INPUT {
+        return Box::new(std::iter::once(Input::Stdin));
+    }
+    // The walker's own error for this is rather wordy.
+    if let Err(err) = std::fs::metadata(input) {
+        report(format!("{}: {}", input, err));
+        return Box::new(std::iter::empty());
+    }
+    Box::new(new_walker(opts, input).filter_map(|entry| {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                report(err);
+                return None;
+            }
+        };
+        if entry.file_type().is_none_or(|t| t.is_dir()) {
+            return None;
+        }
+        let walked = entry.depth() > 0;
+        Some(Input::File { path: entry.into_path(), walked })
+    }))
+}
+
+// Searches one input and prints whatever `opts.mode` asks for, returning
+// the number of matching lines.
+fn search_input<W: Write>(
+    input: &Input,
+    out: &mut W,
+    searcher: &Searcher,
+    matcher: &Matcher,
+    opts: &Opts,
+) -> u64 {
+    let (name, result) = match input {
+        Input::Stdin if opts.in_place => {
+            report(format!("{}: can't be edited in place", STDIN_LABEL));
+            return 0;
+        }
+        Input::Stdin => {
+            let result = open(io::stdin().lock(), None, opts).and_then(|reader| {
+                search(out, STDIN_LABEL, matcher, opts, |sink| {
+                    searcher.search_reader(matcher, reader, sink)
+                })
+            });
+            (STDIN_LABEL.to_string(), result)
+        }
+        Input::File { path, .. } if opts.in_place => {
+            (path.display().to_string(), replace_in_place(path, matcher, opts))
+        }
+        Input::File { path, walked } => {
+            let name = path.display().to_string();
+            match map_file(path, opts) {
+                Ok(Some(data)) => {
+                    if *walked && !opts.text && binary_offset_head(&data).is_some() {
+                        return 0;
+                    }
+                    let result = search(out, &name, matcher, opts, |sink| {
+                        searcher.search_slice(matcher, &data, sink)
+                    });
+                    return finish(out, opts, name, result);
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    report(format!("{}: {}", name, err));
+                    return 0;
+                }
+            }
+            let opened = File::open(path).and_then(|f| open(BufReader::new(f), Some(path), opts));
+            let mut reader = match opened {
+                Ok(reader) => reader,
+                Err(err) => {
+                    report(format!("{}: {}", name, err));
+                    return 0;
+                }
+            };
+            // Files found by walking a directory are skipped if they look
+            // binary; ones named on the command line are always searched.
+            if *walked && !opts.text && looks_binary(&mut reader) {
+                return 0;
+            }
+            let result = search(out, &name, matcher, opts, |sink| {
+                searcher.search_reader(matcher, reader, sink)
+            });
+            (name, result)
+        }
+    };
+    finish(out, opts, name, result)
+}
+
+// Prints what -c, -l or -L print once a file has been searched.
+fn finish<W: Write>(out: &mut W, opts: &Opts, name: String, result: io::Result<u64>) -> u64 {
+    let count = match result {
+        Ok(count) => count,
+        Err(err) => {
+            report(format!("{}: {}", name, err));
+            return 0;
+        }
+    };
+    // As with `print`, write errors are noticed by `main`.
+    let colors = &opts.print.colors;
+    let name = colors.paint(&colors.file_name, &name);
+    let _ = match opts.mode {
+        Mode::Count if opts.print.with_filename => {
+            let delim = colors.paint(&colors.separator, MATCH_DELIM);
+            writeln!(out, "{}{}{}", name, delim, count)
+        }
+        Mode::Count => writeln!(out, "{}", count),
+        Mode::FilesWithMatches if count > 0 => writeln!(out, "{}", name),
+        Mode::FilesWithoutMatch if count == 0 => writeln!(out, "{}", name),
+        _ => Ok(()),
+    };
+    count
+}
+
+// Maps `path` into memory if --mmap says to and nothing needs the file to
+// be read through a decoder.
+fn map_file(path: &Path, opts: &Opts) -> io::Result<Option<Mmap>> {
+    if opts.mmap == Some(false) || opts.search_zip || opts.encoding.is_some() {
+        return Ok(None);
+    }
+    let file = File::open(path)?;
+    let meta = file.metadata()?;
+    // Pipes and devices can't be mapped, and empty files needn't be.
+    if !meta.is_file() || meta.len() == 0 || (opts.mmap.is_none() && meta.len() < MMAP_MIN_LEN) {
+        return Ok(None);
+    }
+    // Safety: the map is only read from. If another process truncates the
+    // file while it's mapped, this process may be killed by SIGBUS, which is
+    // the same risk grep and ripgrep take.
+    let data = unsafe { Mmap::map(&file)? };
+    // Leave text with a byte order mark to be transcoded.
+    if BOMS.iter().any(|bom| data.starts_with(bom)) {
+        return Ok(None);
+    }
+    Ok(Some(data))
+}
+
+// Runs `search` with the printer that `opts.mode` calls for, returning the
+// number of matching lines.
+fn search<W, F>(out: &mut W, name: &str, matcher: &Matcher, opts: &Opts, search: F) -> io::Result<u64>
+where
+    W: Write,
+    F: FnOnce(&mut dyn Sink) -> io::Result<SearchStats>,
+{
+    let stats = match opts.mode {
+        Mode::Lines => search(&mut StandardSink::new(out, name, matcher, &opts.print))?,
+        Mode::Json => search(&mut JsonSink::new(out, name, matcher))?,
+        _ => search(&mut Discard)?,
+    };
+    Ok(stats.matched_lines)
+}
+
+// For the modes that only need to know how many lines matched, which the
+// searcher counts itself.
+struct Discard;
+
+impl Sink for Discard {
+    fn matched(&mut self, _line: &Line) -> io::Result<()> {
+        Ok(())
     }
 }
 
 fn parse_args() -> ArgMatches {
     App::new("grep-lite")
         .version("0.1")
-        .about("Search for PATTERN in FILE")
+        .about("Search for PATTERN in each FILE, recursing into directories")
         .arg(Arg::new(ARG_PATTERN)
-            .help("The pattern to search for")
+            .help("The pattern to search for, unless -e or -f is given")
             .takes_value(true)
-            .required(true))
+            .required_unless_present_any([ARG_REGEXP, ARG_PATTERN_FILE]))
         .arg(Arg::new(ARG_FILE)
-            .help("The file to search in")
+            .help("Files or directories to search in, or - for stdin (default)")
             .takes_value(true)
-            .required(true))
+            .multiple_values(true)
+            .required(false))
         .arg(Arg::new(ARG_RE_MODE)
-            .help("PATTERN is a basic regular expression (default)")
+            .help("PATTERN is a regular expression (default)")
             .takes_value(false)
             .required(false)
-            .default_value("true")
+            .overrides_with(ARG_FIXED)
             .long("basic-regexp")
             .short('G'))
+        .arg(Arg::new(ARG_FIXED)
+            .help("PATTERN is a plain string rather than a regular expression")
+            .takes_value(false)
+            .required(false)
+            .overrides_with(ARG_RE_MODE)
+            .long("fixed-strings")
+            .short('F'))
+        .arg(Arg::new(ARG_REGEXP)
+            .help("search for PATTERN; may be given more than once")
+            .value_name("PATTERN")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .multiple_occurrences(true)
+            .required(false)
+            .long("regexp")
+            .short('e'))
+        .arg(Arg::new(ARG_PATTERN_FILE)
+            .help("search for each pattern in FILE, one per line")
+            .value_name("FILE")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .required(false)
+            .long("file")
+            .short('f'))
+        .arg(Arg::new(ARG_IGNORE_CASE)
+            .help("ignore case distinctions in patterns and data")
+            .takes_value(false)
+            .required(false)
+            .long("ignore-case")
+            .short('i'))
+        .arg(Arg::new(ARG_INVERT)
+            .help("select non-matching lines")
+            .takes_value(false)
+            .required(false)
+            .long("invert-match")
+            .short('v'))
+        .arg(Arg::new(ARG_WORD)
+            .help("match only whole words")
+            .takes_value(false)
+            .required(false)
+            .long("word-regexp")
+            .short('w'))
+        .arg(Arg::new(ARG_LINE)
+            .help("match only whole lines")
+            .takes_value(false)
+            .required(false)
+            .long("line-regexp")
+            .short('x'))
         .arg(Arg::new(ARG_LINUM_ON)
             .help("print line number with output lines")
             .takes_value(false)
             .required(false)
-            .default_value("false")
-            .default_missing_value("true")
             .long("line-number")
             .short('n'))
         .arg(Arg::new(ARG_CTX)
             .help("print NUM lines of output context")
+            .value_name("NUM")
             .takes_value(true)
             .required(false)
-            .default_value("0")
             .long("context")
             .short('C'))
-        .get_matches() 
+        .arg(Arg::new(ARG_BEFORE)
+            .help("print NUM lines of leading context")
+            .value_name("NUM")
+            .takes_value(true)
+            .required(false)
+            .long("before-context")
+            .short('B'))
+        .arg(Arg::new(ARG_AFTER)
+            .help("print NUM lines of trailing context")
+            .value_name("NUM")
+            .takes_value(true)
+            .required(false)
+            .long("after-context")
+            .short('A'))
+        .arg(Arg::new(ARG_GROUP_SEPARATOR)
+            .help("print SEP between groups of context lines (default: --)")
+            .value_name("SEP")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .required(false)
+            .long("group-separator"))
+        .arg(Arg::new(ARG_NO_GROUP_SEPARATOR)
+            .help("print nothing between groups of context lines")
+            .takes_value(false)
+            .required(false)
+            .overrides_with(ARG_GROUP_SEPARATOR)
+            .long("no-group-separator"))
+        .arg(Arg::new(ARG_GLOB)
+            .help("only search files matching GLOB, or not matching it if it starts with !")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .required(false)
+            .long("glob")
+            .short('g'))
+        .arg(Arg::new(ARG_TYPE)
+            .help("only search files of TYPE, such as rust or py")
+            .takes_value(true)
+            .multiple_occurrences(true)
+            .required(false)
+            .long("type")
+            .short('t'))
+        .arg(Arg::new(ARG_HIDDEN)
+            .help("search hidden files and directories")
+            .takes_value(false)
+            .required(false)
+            .long("hidden"))
+        .arg(Arg::new(ARG_NO_IGNORE)
+            .help("don't skip files listed in .gitignore or .ignore")
+            .takes_value(false)
+            .required(false)
+            .long("no-ignore"))
+        .arg(Arg::new(ARG_THREADS)
+            .help("search NUM files at once (default: the number of CPUs)")
+            .takes_value(true)
+            .required(false)
+            .long("threads")
+            .short('j'))
+        .arg(Arg::new(ARG_SORT)
+            .help("print results in path order rather than as files finish")
+            .takes_value(true)
+            .possible_values(["path", "none"])
+            .required(false)
+            .long("sort"))
+        .arg(Arg::new(ARG_COUNT)
+            .help("print only a count of matching lines per file")
+            .takes_value(false)
+            .required(false)
+            .long("count")
+            .short('c'))
+        .arg(Arg::new(ARG_FILES_WITH_MATCHES)
+            .help("print only the names of files with matches")
+            .takes_value(false)
+            .required(false)
+            .long("files-with-matches")
+            .short('l'))
+        .arg(Arg::new(ARG_FILES_WITHOUT_MATCH)
+            .help("print only the names of files without matches")
+            .takes_value(false)
+            .required(false)
+            .long("files-without-match")
+            .short('L'))
+        .arg(Arg::new(ARG_QUIET)
+            .help("print nothing; exit with status 0 on the first match")
+            .takes_value(false)
+            .required(false)
+            .long("quiet")
+            .alias("silent")
+            .short('q'))
+        .arg(Arg::new(ARG_MAX_COUNT)
+            .help("stop reading a file after NUM matching lines")
+            .value_name("NUM")
+            .takes_value(true)
+            .required(false)
+            .long("max-count")
+            .short('m'))
+        .arg(Arg::new(ARG_COLOR)
+            .help("highlight matches, file names, line numbers and separators")
+            .value_name("WHEN")
+            .takes_value(true)
+            .possible_values(["auto", "always", "never"])
+            .required(false)
+            .long("color")
+            .alias("colour"))
+        .arg(Arg::new(ARG_ONLY_MATCHING)
+            .help("print only the matched parts of lines, one per line")
+            .takes_value(false)
+            .required(false)
+            .long("only-matching")
+            .short('o'))
+        .arg(Arg::new(ARG_JSON)
+            .help("print results as JSON lines, one object per event")
+            .takes_value(false)
+            .required(false)
+            .conflicts_with_all(&[ARG_ONLY_MATCHING, ARG_COUNT, ARG_FILES_WITH_MATCHES,
+                ARG_FILES_WITHOUT_MATCH])
+            .long("json"))
+        .arg(Arg::new(ARG_TEXT)
+            .help("search binary files as if they were text")
+            .takes_value(false)
+            .required(false)
+            .long("text")
+            .short('a'))
+        .arg(Arg::new(ARG_ENCODING)
+            .help("decode input from ENCODING, such as utf-16le or latin1")
+            .value_name("ENCODING")
+            .takes_value(true)
+            .required(false)
+            .long("encoding"))
+        .arg(Arg::new(ARG_REPLACE)
+            .help("print selected lines with each match replaced by TEMPLATE, which may \
+                   use $1 or ${name} for what a group matched")
+            .value_name("TEMPLATE")
+            .takes_value(true)
+            .allow_hyphen_values(true)
+            .required(false)
+            .conflicts_with(ARG_JSON)
+            .long("replace"))
+        .arg(Arg::new(ARG_IN_PLACE)
+            .help("write the replacements back to the files, keeping a copy of each \
+                   original with SUFFIX added to its name if one is given")
+            .value_name("SUFFIX")
+            .takes_value(true)
+            .min_values(0)
+            .require_equals(true)
+            .required(false)
+            .requires(ARG_REPLACE)
+            .conflicts_with(ARG_QUIET)
+            .long("in-place"))
+        .arg(Arg::new(ARG_MULTILINE)
+            .help("let matches span lines, by matching against the whole of each file")
+            .takes_value(false)
+            .required(false)
+            .conflicts_with(ARG_REPLACE)
+            .long("multiline")
+            .short('U'))
+        .arg(Arg::new(ARG_MULTILINE_DOTALL)
+            .help("with -U, let . match line endings too")
+            .takes_value(false)
+            .required(false)
+            .requires(ARG_MULTILINE)
+            .long("multiline-dotall"))
+        .arg(Arg::new(ARG_SEARCH_ZIP)
+            .help("search inside gzip, zstd, xz and bzip2 files")
+            .takes_value(false)
+            .required(false)
+            .conflicts_with(ARG_IN_PLACE)
+            .long("search-zip")
+            .short('z'))
+        .arg(Arg::new(ARG_MMAP)
+            .help("memory-map files rather than reading them, however small")
+            .takes_value(false)
+            .required(false)
+            .overrides_with(ARG_NO_MMAP)
+            .long("mmap"))
+        .arg(Arg::new(ARG_NO_MMAP)
+            .help("never memory-map files")
+            .takes_value(false)
+            .required(false)
+            .overrides_with(ARG_MMAP)
+            .long("no-mmap"))
+        .arg(Arg::new(ARG_FOLLOW)
+            .help("keep searching files as they grow, like tail -f, through truncation and rotation")
+            .takes_value(false)
+            .required(false)
+            .conflicts_with_all(&[ARG_COUNT, ARG_FILES_WITH_MATCHES, ARG_FILES_WITHOUT_MATCH,
+                ARG_JSON, ARG_IN_PLACE, ARG_MULTILINE, ARG_SEARCH_ZIP])
+            .long("follow"))
+        .get_matches()
 }
 
-fn new_reader<'a>(args: &ArgMatches, stdin: &'a io::Stdin) -> Box<dyn BufRead + 'a> {
-    let input = args.value_of(ARG_FILE).unwrap();
-    if input == STDIN_INPUT {
-        Box::new(stdin.lock())
-    } else {
-        let f = File::open(input)
-            .expect(&format!("{}: No such file or directory", input));
-        Box::new(BufReader::new(f))
+fn new_walker(opts: &WalkOpts, input: &str) -> ignore::Walk {
+    let mut overrides = OverrideBuilder::new(input);
+    for glob in &opts.globs {
+        // Already checked by `walk_opts`.
+        overrides.add(glob).unwrap();
+    }
+    let no_ignore = opts.no_ignore;
+    let mut builder = WalkBuilder::new(input);
+    if opts.sorted {
+        builder.sort_by_file_name(|a, b| a.cmp(b));
+    }
+    builder
+        .hidden(!opts.hidden)
+        .ignore(!no_ignore)
+        .git_ignore(!no_ignore)
+        .git_global(!no_ignore)
+        .git_exclude(!no_ignore)
+        .parents(!no_ignore)
+        // Honor .gitignore files even outside a git checkout.
+        .require_git(false)
+        .overrides(overrides.build().unwrap())
+        .types(opts.types.clone())
+        .build()
+}
+
+// Rewrites the selected lines of `path` with --replace, returning how many
+// there were. The new contents are written to a file beside it that is then
+// renamed over it, so that nothing ever sees the file half written.
+fn replace_in_place(path: &Path, matcher: &Matcher, opts: &Opts) -> io::Result<u64> {
+    let rep = opts.print.replace.as_ref().expect("--in-place requires --replace");
+    let contents = fs::read(path)?;
+    if !opts.text && binary_offset(&contents).is_some() {
+        eprintln!("grep-lite: {}: binary file not changed", path.display());
+        return Ok(0);
+    }
+    let mut rewrite = Rewrite {
+        contents: &contents,
+        changed: Vec::with_capacity(contents.len()),
+        copied: 0,
+        matcher,
+        rep,
+    };
+    let stats = Searcher::new()
+        .max_count(opts.max_count)
+        .search_slice(matcher, &contents, &mut rewrite)?;
+    let mut changed = rewrite.changed;
+    changed.extend_from_slice(&contents[rewrite.copied..]);
+    if changed == contents {
+        return Ok(stats.matched_lines);
+    }
+
+    let tmp = with_suffix(path, ".grep-lite-tmp");
+    let replaced = fs::write(&tmp, &changed)
+        .and_then(|_| fs::set_permissions(&tmp, fs::metadata(path)?.permissions()))
+        .and_then(|_| File::open(&tmp)?.sync_all())
+        .and_then(|_| match &opts.backup_suffix {
+            Some(suffix) => fs::copy(path, with_suffix(path, suffix)).map(drop),
+            None => Ok(()),
+        })
+        .and_then(|_| fs::rename(&tmp, path));
+    if let Err(err) = replaced {
+        let _ = fs::remove_file(&tmp);
+        return Err(err);
+    }
+    Ok(stats.matched_lines)
+}
+
+// Copies a file's contents with each selected line replaced.
+struct Rewrite<'a> {
+    contents: &'a [u8],
+    changed: Vec<u8>,
+    // How much of `contents` has been dealt with.
+    copied: usize,
+    matcher: &'a Matcher,
+    rep: &'a Replacement,
+}
+
+impl Sink for Rewrite<'_> {
+    fn matched(&mut self, line: &Line) -> io::Result<()> {
+        let start = line.offset as usize;
+        self.changed.extend_from_slice(&self.contents[self.copied..start]);
+        let content = line.content();
+        self.changed.extend_from_slice(&self.matcher.replace(content, self.rep).0);
+        self.changed.extend_from_slice(&line.text[content.len()..]);
+        self.copied = start + line.text.len();
+        Ok(())
     }
 }
 
-fn print(line: &str, line_num: usize, is_match: bool, need_line_num: bool) {
-    let num: &str;
-    let owned_num_str;
-    let del: &str;
-    if need_line_num {
-        owned_num_str = line_num.to_string();
-        num = &owned_num_str;
-        del = delim(is_match);
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut p: OsString = path.to_path_buf().into_os_string();
+    p.push(suffix);
+    PathBuf::from(p)
+}
+
+// Searches each of `inputs` with --follow, each in a thread of its own as
+// they may be written to at any time, and exits once none of them can have
+// anything more to print, which without -m is only when they all fail.
+fn follow(inputs: &[&str], searcher: &Searcher, matcher: &Matcher, opts: &Opts) -> ! {
+    let matched = AtomicBool::new(false);
+    thread::scope(|s| {
+        for input in inputs {
+            let matched = &matched;
+            s.spawn(move || {
+                if follow_input(input, searcher, matcher, opts) > 0 {
+                    matched.store(true, Ordering::Relaxed);
+                    if opts.mode == Mode::Quiet {
+                        process::exit(EXIT_MATCH);
+                    }
+                }
+            });
+        }
+    });
+    process::exit(if HAD_ERROR.load(Ordering::Relaxed) {
+        EXIT_ERROR
+    } else if matched.load(Ordering::Relaxed) {
+        EXIT_MATCH
     } else {
-        num = EMPTY_STR;
-        del = EMPTY_STR;
+        EXIT_NO_MATCH
+    });
+}
+
+fn follow_input(input: &str, searcher: &Searcher, matcher: &Matcher, opts: &Opts) -> u64 {
+    let mut out = LineOut(Vec::new());
+    let (name, result) = if input == STDIN_INPUT {
+        // A pipe already waits for more to be written to it.
+        let result = decode(io::stdin().lock(), opts).and_then(|reader| {
+            search(&mut out, STDIN_LABEL, matcher, opts, |sink| {
+                searcher.search_reader(matcher, reader, sink)
+            })
+        });
+        (STDIN_LABEL, result)
+    } else {
+        let result = Follow::open(Path::new(input), input)
+            .and_then(|file| decode(file, opts))
+            .and_then(|reader| {
+                search(&mut out, input, matcher, opts, |sink| {
+                    searcher.search_reader(matcher, reader, sink)
+                })
+            });
+        (input, result)
+    };
+    match result {
+        Ok(count) => count,
+        // The reader has gone away, which it can only have done after
+        // something was printed.
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => process::exit(EXIT_MATCH),
+        Err(err) => {
+            report(format!("{}: {}", name, err));
+            0
+        }
     }
-    println!("{}{}{}", num, del, line);
 }
 
-const fn delim(is_match: bool) -> &'static str {
-    if is_match {
-        MATCH_DELIM
+// Output for --follow, which is written out as soon as each line is done,
+// in one piece so that lines from files followed at the same time can't run
+// into each other.
+struct LineOut(Vec<u8>);
+
+impl Write for LineOut {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        if buf.ends_with(b"\n") {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut out = io::stdout().lock();
+        out.write_all(&self.0)?;
+        out.flush()?;
+        self.0.clear();
+        Ok(())
+    }
+}
+
+// Gets input ready to be searched: decompressed if -z is given and it's
+// compressed, then transcoded.
+fn open<'a, R: BufRead + 'a>(
+    mut reader: R,
+    path: Option<&Path>,
+    opts: &Opts,
+) -> io::Result<impl BufRead + 'a> {
+    let compression = if opts.search_zip {
+        Compression::detect(path, reader.fill_buf()?)
     } else {
-        CTX_DELIM
+        None
+    };
+    let reader: Box<dyn Read + 'a> = match compression {
+        None => Box::new(reader),
+        Some(compression) => compression.decoder(reader)?,
+    };
+    decode(reader, opts)
+}
+
+// Transcodes input to UTF-8 as --encoding asks. Without it, input is passed
+// through untouched unless it starts with a UTF-16 byte order mark.
+fn decode<R: Read>(reader: R, opts: &Opts) -> io::Result<impl BufRead> {
+    let mut decoder = DecodeReaderBytesBuilder::new()
+        .encoding(opts.encoding)
+        .build(reader);
+    // The decoder hands out the first few bytes on their own while it looks
+    // for a byte order mark, so read past them for `find_nul` to have a whole
+    // buffer to look at. Reading any further could wait on a slow pipe.
+    let mut head = vec![0; BINARY_CHECK_LEN];
+    let mut len = 0;
+    loop {
+        let n = decoder.read(&mut head[len..])?;
+        len += n;
+        if n == 0 || len > BOM_MAX_LEN {
+            break;
+        }
     }
+    head.truncate(len);
+    Ok(BufReader::with_capacity(BINARY_CHECK_LEN, io::Cursor::new(head).chain(decoder)))
 }
 
+// Whether the start of the input has a NUL byte in it, without consuming
+// anything.
+fn looks_binary<R: BufRead>(reader: &mut R) -> bool {
+    matches!(reader.fill_buf(), Ok(buf) if binary_offset(buf).is_some())
+}
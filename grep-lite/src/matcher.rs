@@ -0,0 +1,318 @@
+use std::collections::BTreeMap;
+
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use memchr::memmem;
+use regex::bytes::{CaptureLocations, Regex, RegexBuilder};
+
+// For -U, the parts of each line that matches cover, by line number. Every
+// line a match touches is a matching line, even if only its line ending is
+// part of the match.
+pub(crate) type LineSpans = BTreeMap<u64, Vec<(usize, usize)>>;
+
+// Drops the line ending, as `BufRead::lines` would.
+pub(crate) fn strip_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// How patterns are interpreted. Each field is one of grep's match selection
+/// flags, and all of them are off by default.
+#[derive(Clone, Debug, Default)]
+pub struct MatchOpts {
+    /// Patterns are plain strings rather than regular expressions (-F).
+    pub fixed: bool,
+    /// -i
+    pub ignore_case: bool,
+    /// Patterns only match whole words (-w).
+    pub word: bool,
+    /// Patterns only match whole lines (-x).
+    pub line: bool,
+    /// Select the lines that don't match instead (-v).
+    pub invert: bool,
+    /// Match against the whole input rather than a line at a time (-U).
+    pub multiline: bool,
+    /// With `multiline`, let `.` match line endings too.
+    pub dotall: bool,
+}
+
+/// Decides which lines are selected and where the matches in them are.
+pub struct Matcher {
+    // `None` when there are no patterns at all, which matches nothing.
+    re: Option<Regex>,
+    // The capture group holding what the patterns matched, which for -w
+    // leaves out the characters either side of the word.
+    group: usize,
+    invert: bool,
+    pub(crate) multiline: bool,
+    // Set when the patterns are plain strings that can be looked for
+    // without the regex.
+    pub(crate) literals: Option<Literals>,
+}
+
+impl Matcher {
+    /// A line matches if any of `terms` matches it. With no terms at all,
+    /// nothing matches.
+    pub fn new(terms: &[String], opts: &MatchOpts) -> Result<Matcher, regex::Error> {
+        let re = if terms.is_empty() {
+            None
+        } else {
+            Some(Matcher::parse_re(terms, opts)?)
+        };
+        let group = if opts.word && !opts.line { 1 } else { 0 };
+        Ok(Matcher {
+            re,
+            group,
+            invert: opts.invert,
+            multiline: opts.multiline,
+            literals: Literals::new(terms, opts),
+        })
+    }
+
+    /// Whether the line `s`, without its line ending, is selected.
+    pub fn matches(&self, s: &[u8]) -> bool {
+        let found = match &self.re {
+            Some(re) => re.is_match(s),
+            None => false,
+        };
+        found != self.invert
+    }
+
+    /// Byte ranges of the non-empty matches in `s`, ignoring `invert`.
+    pub fn find_spans(&self, s: &[u8]) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        self.each_match(s, |locs| spans.push(locs.get(self.group).unwrap()));
+        spans
+    }
+
+    /// Whether lines that don't match are the ones selected.
+    pub fn invert(&self) -> bool {
+        self.invert
+    }
+
+    /// `s` with each non-empty match replaced by `rep`, and where the
+    /// replacements ended up in it.
+    pub fn replace(&self, s: &[u8], rep: &Replacement) -> (Vec<u8>, Vec<(usize, usize)>) {
+        let mut out = Vec::with_capacity(s.len());
+        let mut spans = Vec::new();
+        let mut last = 0;
+        self.each_match(s, |locs| {
+            let (start, end) = locs.get(self.group).unwrap();
+            out.extend_from_slice(&s[last..start]);
+            let from = out.len();
+            for piece in &rep.pieces {
+                match piece {
+                    Piece::Literal(text) => out.extend_from_slice(text),
+                    Piece::Group(Some(i)) => {
+                        if let Some((start, end)) = locs.get(*i) {
+                            out.extend_from_slice(&s[start..end]);
+                        }
+                    }
+                    Piece::Group(None) => {}
+                }
+            }
+            spans.push((from, out.len()));
+            last = end;
+        });
+        out.extend_from_slice(&s[last..]);
+        (out, spans)
+    }
+
+    // The group in the regex that `name` (a number or a name) refers to in
+    // the patterns as they were given.
+    fn group_index(&self, name: &str) -> Option<usize> {
+        let re = self.re.as_ref()?;
+        let index = match name.parse::<usize>() {
+            // -w puts the patterns inside a group of their own.
+            Ok(n) => n + self.group,
+            Err(_) => re.capture_names().position(|n| n == Some(name))?,
+        };
+        (index < re.captures_len()).then_some(index)
+    }
+
+    // Runs the patterns over the whole of `data`, for -U.
+    pub(crate) fn multiline_spans(&self, data: &[u8]) -> LineSpans {
+        let mut spans = LineSpans::new();
+        let mut line_num = 1;
+        let mut line_start = 0;
+        // How far through `data` lines have been counted.
+        let mut counted = 0;
+        self.each_match(data, |locs| {
+            let (start, end) = locs.get(self.group).unwrap();
+            for (i, _) in data[counted..start].iter().enumerate().filter(|(_, &b)| b == b'\n') {
+                line_num += 1;
+                line_start = counted + i + 1;
+            }
+            let mut pos = start;
+            loop {
+                let line_end = data[pos..]
+                    .iter()
+                    .position(|&b| b == b'\n')
+                    .map_or(data.len(), |i| pos + i);
+                let line_spans = spans.entry(line_num).or_default();
+                if pos < end.min(line_end) {
+                    line_spans.push((pos - line_start, end.min(line_end) - line_start));
+                }
+                // Done if the match ends in this line, or with its line ending.
+                if end <= line_end + 1 {
+                    break;
+                }
+                line_num += 1;
+                line_start = line_end + 1;
+                pos = line_start;
+            }
+            counted = pos;
+        });
+        spans
+    }
+
+    fn each_match<F: FnMut(&CaptureLocations)>(&self, s: &[u8], mut f: F) {
+        let re = match &self.re {
+            Some(re) => re,
+            None => return,
+        };
+        let mut locs = re.capture_locations();
+        let mut pos = 0;
+        while pos <= s.len() {
+            if re.captures_read_at(&mut locs, s, pos).is_none() {
+                break;
+            }
+            let (start, end) = locs.get(self.group).unwrap();
+            if start < end {
+                f(&locs);
+                // Carry on from the end of the word rather than the whole
+                // match, so that the non-word character after it can start
+                // the next one.
+                pos = end;
+            } else {
+                pos = end + 1;
+            }
+        }
+    }
+
+    fn parse_re(terms: &[String], opts: &MatchOpts) -> Result<Regex, regex::Error> {
+        let alternatives: Vec<String> = terms
+            .iter()
+            .map(|term| {
+                let term = if opts.fixed { regex::escape(term) } else { term.clone() };
+                format!("(?:{})", term)
+            })
+            .collect();
+        let mut pattern = alternatives.join("|");
+        if opts.line {
+            pattern = format!("^(?:{})$", pattern);
+        } else if opts.word {
+            // Like grep, a word match needs a non-word character or the edge
+            // of the line on either side.
+            pattern = format!(r"(?:^|\W)({})(?:\W|$)", pattern);
+        }
+        RegexBuilder::new(&pattern)
+            .case_insensitive(opts.ignore_case)
+            // So that ^ and $ still mean the start and end of a line.
+            .multi_line(opts.multiline)
+            .dot_matches_new_line(opts.dotall)
+            .build()
+    }
+}
+
+// Finds patterns that are all plain strings across a whole buffer at once,
+// which is much faster than running the regex over it a line at a time.
+pub(crate) enum Literals {
+    One(memmem::Finder<'static>),
+    Many(AhoCorasick),
+}
+
+impl Literals {
+    // `None` if anything about the patterns needs the regex. -i is left to
+    // the regex as it folds case by Unicode's rules.
+    fn new(terms: &[String], opts: &MatchOpts) -> Option<Literals> {
+        if opts.ignore_case || opts.word || opts.line || opts.multiline {
+            return None;
+        }
+        let is_literal = |t: &String| !t.is_empty() && (opts.fixed || regex::escape(t) == *t);
+        if terms.is_empty() || !terms.iter().all(is_literal) {
+            return None;
+        }
+        Some(match terms {
+            [term] => Literals::One(memmem::Finder::new(term).into_owned()),
+            // Leftmost-first, as with the regex's alternation.
+            _ => Literals::Many(
+                AhoCorasickBuilder::new()
+                    .match_kind(MatchKind::LeftmostFirst)
+                    .build(terms),
+            ),
+        })
+    }
+
+    // The first match at or after `pos`.
+    pub fn find_at(&self, haystack: &[u8], pos: usize) -> Option<(usize, usize)> {
+        let (start, end) = match self {
+            Literals::One(finder) => {
+                let start = finder.find(&haystack[pos..])?;
+                (start, start + finder.needle().len())
+            }
+            Literals::Many(ac) => {
+                let m = ac.find(&haystack[pos..])?;
+                (m.start(), m.end())
+            }
+        };
+        Some((pos + start, pos + end))
+    }
+}
+
+/// A --replace template, with the groups it refers to already looked up.
+pub struct Replacement {
+    pieces: Vec<Piece>,
+}
+
+enum Piece {
+    Literal(Vec<u8>),
+    // `None` for a group the patterns don't have, which expands to nothing.
+    Group(Option<usize>),
+}
+
+impl Replacement {
+    /// `$1` or `${1}` is what the first group matched, `$name` or `${name}`
+    /// the group with that name, and `$0` the whole match, as in the regex
+    /// crate's own templates; `$$` is a plain dollar sign. Groups that
+    /// `matcher` doesn't have expand to nothing.
+    pub fn new(template: &str, matcher: &Matcher) -> Replacement {
+        let mut pieces = Vec::new();
+        let mut literal = Vec::new();
+        let mut rest = template;
+        while let Some(i) = rest.find('$') {
+            literal.extend_from_slice(&rest.as_bytes()[..i]);
+            rest = &rest[i + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                literal.push(b'$');
+                rest = after;
+                continue;
+            }
+            let (name, after) = match rest.strip_prefix('{') {
+                Some(braced) => match braced.find('}') {
+                    Some(end) => (&braced[..end], &braced[end + 1..]),
+                    None => ("", rest),
+                },
+                None => {
+                    let end = rest
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                        .unwrap_or(rest.len());
+                    rest.split_at(end)
+                }
+            };
+            if name.is_empty() {
+                literal.push(b'$');
+                continue;
+            }
+            if !literal.is_empty() {
+                pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+            }
+            pieces.push(Piece::Group(matcher.group_index(name)));
+            rest = after;
+        }
+        literal.extend_from_slice(rest.as_bytes());
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        Replacement { pieces }
+    }
+}
@@ -0,0 +1,415 @@
+use std::borrow::Cow;
+use std::io::{self, prelude::*};
+use std::time::Instant;
+
+use serde_json::{json, Value};
+
+use crate::matcher::{Matcher, Replacement};
+use crate::searcher::{Line, SearchStats, Sink};
+
+/// Follows the file name and line number of a matching line.
+pub const MATCH_DELIM: &str = ":";
+const CTX_DELIM: &str = "-";
+
+/// ANSI SGR sequences for each part of the output, as in GNU grep's
+/// GREP_COLORS. An empty string leaves that part uncolored.
+#[derive(Clone)]
+pub struct Colors {
+    pub selected_match: String,
+    pub context_match: String,
+    pub selected_line: String,
+    pub context_line: String,
+    pub file_name: String,
+    pub line_num: String,
+    pub separator: String,
+    /// Swap the line colors with -v.
+    pub reverse: bool,
+    /// Don't clear to the end of the line after each sequence.
+    pub no_erase: bool,
+}
+
+impl Default for Colors {
+    fn default() -> Colors {
+        Colors::none()
+    }
+}
+
+impl Colors {
+    /// No colors at all.
+    pub fn none() -> Colors {
+        Colors {
+            selected_match: String::new(),
+            context_match: String::new(),
+            selected_line: String::new(),
+            context_line: String::new(),
+            file_name: String::new(),
+            line_num: String::new(),
+            separator: String::new(),
+            reverse: false,
+            no_erase: false,
+        }
+    }
+
+    /// GNU grep's defaults, overridden by anything set in `spec`, which is
+    /// in the same format as GREP_COLORS.
+    pub fn parse(spec: &str) -> Colors {
+        let mut colors = Colors {
+            selected_match: "01;31".to_string(),
+            context_match: "01;31".to_string(),
+            file_name: "35".to_string(),
+            line_num: "32".to_string(),
+            separator: "36".to_string(),
+            ..Colors::none()
+        };
+        for cap in spec.split(':') {
+            let (name, value) = match cap.split_once('=') {
+                Some((name, value)) => (name, value.to_string()),
+                None => (cap, String::new()),
+            };
+            match name {
+                "mt" => {
+                    colors.selected_match = value.clone();
+                    colors.context_match = value;
+                }
+                "ms" => colors.selected_match = value,
+                "mc" => colors.context_match = value,
+                "sl" => colors.selected_line = value,
+                "cx" => colors.context_line = value,
+                "fn" => colors.file_name = value,
+                "ln" => colors.line_num = value,
+                "se" => colors.separator = value,
+                "rv" => colors.reverse = true,
+                "ne" => colors.no_erase = true,
+                // Unknown capabilities are ignored, as grep does.
+                _ => {}
+            }
+        }
+        colors
+    }
+
+    /// Whether nothing is colored, so lines can be printed as they are.
+    pub fn is_plain(&self) -> bool {
+        [
+            &self.selected_match,
+            &self.context_match,
+            &self.selected_line,
+            &self.context_line,
+            &self.file_name,
+            &self.line_num,
+            &self.separator,
+        ]
+        .iter()
+        .all(|c| c.is_empty())
+    }
+
+    fn start(&self, color: &str, out: &mut Vec<u8>) {
+        if !color.is_empty() {
+            out.extend_from_slice(b"\x1b[");
+            out.extend_from_slice(color.as_bytes());
+            out.push(b'm');
+            if !self.no_erase {
+                out.extend_from_slice(b"\x1b[K");
+            }
+        }
+    }
+
+    fn end(&self, color: &str, out: &mut Vec<u8>) {
+        if !color.is_empty() {
+            out.extend_from_slice(if self.no_erase { b"\x1b[m" } else { b"\x1b[m\x1b[K" });
+        }
+    }
+
+    /// `text` with `color` around it.
+    pub fn paint(&self, color: &str, text: &str) -> String {
+        // Only ASCII is added around `text`, so this is still valid UTF-8.
+        String::from_utf8_lossy(&self.paint_bytes(color, text.as_bytes())).into_owned()
+    }
+
+    pub fn paint_bytes(&self, color: &str, text: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.start(color, &mut out);
+        out.extend_from_slice(text);
+        self.end(color, &mut out);
+        out
+    }
+
+    /// Colors a whole line, with `spans` highlighted within it. Laid out the
+    /// way GNU grep does it, so the output is byte-for-byte the same.
+    pub fn line(
+        &self,
+        line: &[u8],
+        spans: &[(usize, usize)],
+        selected: bool,
+        invert: bool,
+    ) -> Vec<u8> {
+        let (mut line_color, match_color) = if selected {
+            (&self.selected_line, &self.selected_match)
+        } else {
+            (&self.context_line, &self.context_match)
+        };
+        if invert && self.reverse {
+            line_color = if selected { &self.context_line } else { &self.selected_line };
+        }
+        let mut out = Vec::with_capacity(line.len());
+        let mut pos = 0;
+        for &(start, end) in spans {
+            self.start(line_color, &mut out);
+            out.extend_from_slice(&line[pos..start]);
+            self.start(match_color, &mut out);
+            out.extend_from_slice(&line[start..end]);
+            self.end(match_color, &mut out);
+            pos = end;
+        }
+        if pos < line.len() {
+            self.start(line_color, &mut out);
+            out.extend_from_slice(&line[pos..]);
+            self.end(line_color, &mut out);
+        }
+        out
+    }
+}
+
+/// How `StandardSink` prints lines, one field for each of grep's output
+/// flags. Everything is off by default.
+#[derive(Default)]
+pub struct PrintOpts {
+    /// Print the file's name before each line.
+    pub with_filename: bool,
+    pub line_number: bool,
+    /// Print each match on its own line, rather than the lines they're in.
+    pub only_matching: bool,
+    /// Printed between groups of lines that aren't next to each other, if
+    /// there's any context.
+    pub group_separator: Option<String>,
+    pub colors: Colors,
+    /// Print selected lines with their matches replaced.
+    pub replace: Option<Replacement>,
+}
+
+/// Prints lines as grep does, with whatever -n, -H, -o, --color and --replace
+/// ask for.
+pub struct StandardSink<'a, W> {
+    out: &'a mut W,
+    // The file's name for messages, which is given whether or not it's
+    // printed before each line.
+    label: &'a str,
+    name: Option<&'a str>,
+    matcher: &'a Matcher,
+    opts: &'a PrintOpts,
+}
+
+impl<'a, W: Write> StandardSink<'a, W> {
+    /// Prints to `out` what is found in the file called `name`.
+    pub fn new(out: &'a mut W, name: &'a str, matcher: &'a Matcher, opts: &'a PrintOpts) -> Self {
+        StandardSink {
+            out,
+            label: name,
+            name: if opts.with_filename { Some(name) } else { None },
+            matcher,
+            opts,
+        }
+    }
+
+    fn print(&mut self, line: &Line, is_match: bool) -> io::Result<()> {
+        let (opts, matcher) = (self.opts, self.matcher);
+        // Context lines aren't printed with -o, though they still decide
+        // where group separators go.
+        if opts.only_matching && !is_match {
+            return Ok(());
+        }
+        let colors = &opts.colors;
+        let del = colors.paint(&colors.separator, delim(is_match));
+        let mut head = String::new();
+        if let Some(name) = self.name {
+            head.push_str(&colors.paint(&colors.file_name, name));
+            head.push_str(&del);
+        }
+        if opts.line_number {
+            head.push_str(&colors.paint(&colors.line_num, &line.num.to_string()));
+            head.push_str(&del);
+        }
+        // With -v the selected lines are the ones that didn't match, and it's
+        // the context lines that have something to highlight.
+        let matched = is_match != matcher.invert();
+        // With --replace it's the replacements that are highlighted.
+        let (text, replaced) = match &opts.replace {
+            Some(rep) if is_match && matched => {
+                let (text, spans) = matcher.replace(line.content(), rep);
+                (Cow::Owned(text), Some(spans))
+            }
+            _ => (Cow::Borrowed(line.content()), None),
+        };
+        let text = &*text;
+        let find_spans = || replaced.clone().unwrap_or_else(|| line.find_spans(matcher));
+        let out = &mut *self.out;
+        let mut write_line = |text: &[u8]| -> io::Result<()> {
+            out.write_all(head.as_bytes())?;
+            out.write_all(text)?;
+            out.write_all(b"\n")
+        };
+        if opts.only_matching {
+            find_spans().into_iter().try_for_each(|(start, end)| {
+                write_line(&colors.paint_bytes(&colors.selected_match, &text[start..end]))
+            })
+        } else if colors.is_plain() {
+            write_line(text)
+        } else {
+            let spans = if matched { find_spans() } else { Vec::new() };
+            write_line(&colors.line(text, &spans, is_match, matcher.invert()))
+        }
+    }
+}
+
+impl<W: Write> Sink for StandardSink<'_, W> {
+    fn matched(&mut self, line: &Line) -> io::Result<()> {
+        self.print(line, true)
+    }
+
+    fn context(&mut self, line: &Line) -> io::Result<()> {
+        self.print(line, false)
+    }
+
+    fn context_break(&mut self) -> io::Result<()> {
+        let colors = &self.opts.colors;
+        match &self.opts.group_separator {
+            Some(sep) => writeln!(self.out, "{}", colors.paint(&colors.separator, sep)),
+            None => Ok(()),
+        }
+    }
+
+    // Like grep, nothing from a binary file is printed, only a note that it
+    // matched.
+    fn binary_match(&mut self, _offset: u64) -> io::Result<()> {
+        eprintln!("grep-lite: {}: binary file matches", self.label);
+        Ok(())
+    }
+}
+
+/// Prints one file's --json output, which has the same events as ripgrep's:
+/// "begin" before its first line, then "match" and "context" for each line,
+/// then "end" with statistics. A file with nothing to print gets no events at
+/// all.
+pub struct JsonSink<'a, W> {
+    out: &'a mut W,
+    path: &'a str,
+    matcher: &'a Matcher,
+    started: Instant,
+    begun: bool,
+    matches: u64,
+    bytes_printed: u64,
+}
+
+impl<'a, W: Write> JsonSink<'a, W> {
+    /// Prints to `out` what is found in the file at `path`.
+    pub fn new(out: &'a mut W, path: &'a str, matcher: &'a Matcher) -> Self {
+        JsonSink {
+            out,
+            path,
+            matcher,
+            started: Instant::now(),
+            begun: false,
+            matches: 0,
+            bytes_printed: 0,
+        }
+    }
+
+    fn begin(&mut self) -> io::Result<()> {
+        if self.begun {
+            return Ok(());
+        }
+        self.begun = true;
+        self.event("begin", json!({ "path": { "text": self.path } }))
+    }
+
+    fn line(&mut self, line: &Line, is_match: bool) -> io::Result<()> {
+        self.begin()?;
+        // Lines selected with -v have nothing to point at.
+        let spans = if is_match && !self.matcher.invert() {
+            line.find_spans(self.matcher)
+        } else {
+            Vec::new()
+        };
+        self.matches += spans.len() as u64;
+        let submatches: Vec<Value> = spans
+            .iter()
+            .map(|&(start, end)| {
+                json!({
+                    "match": json_data(&line.text[start..end]),
+                    "start": start,
+                    "end": end,
+                })
+            })
+            .collect();
+        let data = json!({
+            "path": { "text": self.path },
+            "lines": json_data(line.text),
+            "line_number": line.num,
+            "absolute_offset": line.offset,
+            "submatches": submatches,
+        });
+        self.event(if is_match { "match" } else { "context" }, data)
+    }
+
+    fn event(&mut self, kind: &str, data: Value) -> io::Result<()> {
+        let event = json!({ "type": kind, "data": data }).to_string();
+        self.bytes_printed += event.len() as u64 + 1;
+        writeln!(self.out, "{}", event)
+    }
+}
+
+impl<W: Write> Sink for JsonSink<'_, W> {
+    fn matched(&mut self, line: &Line) -> io::Result<()> {
+        self.line(line, true)
+    }
+
+    fn context(&mut self, line: &Line) -> io::Result<()> {
+        self.line(line, false)
+    }
+
+    // A binary file that matched has nothing printed but this, so that it
+    // still shows up.
+    fn finish(&mut self, stats: &SearchStats) -> io::Result<()> {
+        if stats.binary_offset.is_some() && stats.matched_lines > 0 {
+            self.begin()?;
+        }
+        if !self.begun {
+            return Ok(());
+        }
+        let elapsed = self.started.elapsed();
+        let data = json!({
+            "path": { "text": self.path },
+            "binary_offset": stats.binary_offset,
+            "stats": {
+                "elapsed": {
+                    "secs": elapsed.as_secs(),
+                    "nanos": elapsed.subsec_nanos(),
+                    "human": format!("{:.6}s", elapsed.as_secs_f64()),
+                },
+                "searches": 1,
+                "searches_with_match": u64::from(stats.matched_lines > 0),
+                "bytes_searched": stats.bytes_searched,
+                "bytes_printed": self.bytes_printed,
+                "matched_lines": stats.matched_lines,
+                "matches": self.matches,
+            },
+        });
+        self.event("end", data)
+    }
+}
+
+// Input as JSON: a string if it is valid UTF-8, or else base64, as ripgrep
+// does it.
+fn json_data(bytes: &[u8]) -> Value {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => json!({ "text": text }),
+        Err(_) => json!({ "bytes": base64::encode(bytes) }),
+    }
+}
+
+const fn delim(is_match: bool) -> &'static str {
+    if is_match {
+        MATCH_DELIM
+    } else {
+        CTX_DELIM
+    }
+}
@@ -0,0 +1,372 @@
+use std::collections::vec_deque::VecDeque;
+use std::io::{self, prelude::*};
+
+use crate::matcher::{strip_newline, LineSpans, Matcher};
+
+/// How much of the input is checked for NUL bytes up front when deciding
+/// whether it is binary, which is also how much `BufReader` reads at a time.
+pub const BINARY_CHECK_LEN: usize = 8 * 1024;
+
+/// A line read from the input.
+pub struct Line<'a> {
+    /// The line with its line ending, if it had one.
+    pub text: &'a [u8],
+    pub num: u64,
+    /// Byte offset of the start of the line in the input.
+    pub offset: u64,
+    // With -U, where the matches that cover this line fall within it, which
+    // can't be worked out from the line alone.
+    spans: Option<&'a [(usize, usize)]>,
+}
+
+impl Line<'_> {
+    /// The line without its line ending.
+    pub fn content(&self) -> &[u8] {
+        strip_newline(self.text)
+    }
+
+    /// Byte ranges of the matches in `content()`.
+    pub fn find_spans(&self, matcher: &Matcher) -> Vec<(usize, usize)> {
+        match self.spans {
+            Some(spans) => spans.to_vec(),
+            None => matcher.find_spans(self.content()),
+        }
+    }
+}
+
+/// Receives what a `Searcher` finds, in the order it appears in the input.
+/// Only `matched` has to be provided; everything else is ignored by default.
+/// An error from any of them ends the search and is returned from it.
+pub trait Sink {
+    /// A line the matcher selected, which with -v is one that didn't match.
+    fn matched(&mut self, line: &Line) -> io::Result<()>;
+
+    /// A line before or after a selected one, within the context asked for.
+    fn context(&mut self, _line: &Line) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Comes between two groups of lines that aren't next to each other in
+    /// the input. Only sent when there is context.
+    fn context_break(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// A line was selected after a NUL byte was seen at `offset`. Nothing
+    /// more is sent for the input apart from `finish`.
+    fn binary_match(&mut self, _offset: u64) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// The search is over.
+    fn finish(&mut self, _stats: &SearchStats) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What a search found, once it's done.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Lines selected, including one that only got a `binary_match`.
+    pub matched_lines: u64,
+    /// How far into the input the search got before it stopped.
+    pub bytes_searched: u64,
+    /// Where the first NUL byte is, if binary detection is on and the
+    /// search got that far.
+    pub binary_offset: Option<u64>,
+}
+
+/// Reads input a line at a time, numbering the lines and working out which
+/// of them are context, and hands them to a `Sink`. By default there is no
+/// context, no limit on matches and no binary detection.
+#[derive(Clone, Debug, Default)]
+pub struct Searcher {
+    before: usize,
+    after: usize,
+    max_count: Option<u64>,
+    binary_detection: bool,
+}
+
+impl Searcher {
+    pub fn new() -> Searcher {
+        Searcher::default()
+    }
+
+    /// Lines of context to send before each selected line.
+    pub fn before_context(mut self, lines: usize) -> Searcher {
+        self.before = lines;
+        self
+    }
+
+    /// Lines of context to send after each selected line.
+    pub fn after_context(mut self, lines: usize) -> Searcher {
+        self.after = lines;
+        self
+    }
+
+    /// Stops after this many selected lines, apart from the context that
+    /// follows the last of them.
+    pub fn max_count(mut self, count: Option<u64>) -> Searcher {
+        self.max_count = count;
+        self
+    }
+
+    /// Treats input with a NUL byte in it as binary, like grep: the first
+    /// `BINARY_CHECK_LEN` bytes are checked up front, and each line after
+    /// that as it is read. Lines from the first NUL on aren't sent, and the
+    /// first one selected gets a `binary_match` instead and ends the search.
+    pub fn binary_detection(mut self, yes: bool) -> Searcher {
+        self.binary_detection = yes;
+        self
+    }
+
+    pub fn search_reader<R: BufRead, S: Sink + ?Sized>(
+        &self,
+        matcher: &Matcher,
+        mut reader: R,
+        sink: &mut S,
+    ) -> io::Result<SearchStats> {
+        // -U matches against the whole input at once, after which its lines
+        // are read back out of it as usual.
+        if matcher.multiline {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            return self.search_multiline(matcher, &data, sink);
+        }
+        self.search_lines(matcher, reader, None, sink)
+    }
+
+    fn search_multiline<S: Sink + ?Sized>(
+        &self,
+        matcher: &Matcher,
+        data: &[u8],
+        sink: &mut S,
+    ) -> io::Result<SearchStats> {
+        let line_spans = matcher.multiline_spans(data);
+        self.search_lines(matcher, data, Some(&line_spans), sink)
+    }
+
+    /// Searches input that is already in memory. When the patterns are
+    /// plain strings, the hits are found across the whole of `data` and only
+    /// the lines holding them are looked at. Anything that needs every line
+    /// to be seen, such as context or -v, is searched a line at a time as
+    /// `search_reader` would.
+    pub fn search_slice<S: Sink + ?Sized>(
+        &self,
+        matcher: &Matcher,
+        data: &[u8],
+        sink: &mut S,
+    ) -> io::Result<SearchStats> {
+        if matcher.multiline {
+            return self.search_multiline(matcher, data, sink);
+        }
+        let literals = match &matcher.literals {
+            Some(literals) => literals,
+            None => return self.search_lines(matcher, data, None, sink),
+        };
+        let has_context = self.before + self.after > 0;
+        let binary_head = self.binary_detection && binary_offset_head(data).is_some();
+        if matcher.invert() || has_context || binary_head {
+            return self.search_lines(matcher, data, None, sink);
+        }
+        let mut count = 0;
+        let mut line_num = 1;
+        // How far lines have been counted, how far they have been checked
+        // for NUL bytes, and where the search has got to.
+        let mut counted = 0;
+        let mut nul_checked = 0;
+        let mut binary = None;
+        let mut pos = 0;
+        let mut spans = Vec::new();
+        while self.max_count != Some(count) {
+            let (start, _) = match literals.find_at(data, pos) {
+                Some(found) => found,
+                None => {
+                    pos = data.len();
+                    break;
+                }
+            };
+            let line_start = memchr::memrchr(b'\n', &data[..start]).map_or(0, |i| i + 1);
+            let line_end = memchr::memchr(b'\n', &data[start..]).map_or(data.len(), |i| start + i + 1);
+            line_num += memchr::memchr_iter(b'\n', &data[counted..line_start]).count() as u64;
+            counted = line_start;
+            pos = line_end;
+            count += 1;
+            // As with `search_lines`, a NUL anywhere up to the end of a
+            // selected line turns it into a binary match.
+            if self.binary_detection {
+                if let Some(i) = binary_offset(&data[nul_checked..line_end]) {
+                    let offset = (nul_checked + i) as u64;
+                    binary = Some(offset);
+                    sink.binary_match(offset)?;
+                    break;
+                }
+                nul_checked = line_end;
+            }
+            let text = &data[line_start..line_end];
+            let content = strip_newline(text);
+            spans.clear();
+            let mut at = start - line_start;
+            while let Some((start, end)) = literals.find_at(content, at) {
+                spans.push((start, end));
+                at = end;
+            }
+            let line = Line { text, num: line_num, offset: line_start as u64, spans: Some(&spans) };
+            sink.matched(&line)?;
+        }
+        let stats = SearchStats {
+            matched_lines: count,
+            bytes_searched: pos as u64,
+            binary_offset: binary,
+        };
+        sink.finish(&stats)?;
+        Ok(stats)
+    }
+
+    fn search_lines<R: BufRead, S: Sink + ?Sized>(
+        &self,
+        matcher: &Matcher,
+        mut reader: R,
+        line_spans: Option<&LineSpans>,
+        sink: &mut S,
+    ) -> io::Result<SearchStats> {
+        let mut stats = SearchStats::default();
+        if self.max_count == Some(0) {
+            sink.finish(&stats)?;
+            return Ok(stats);
+        }
+        let spans_of = |num: u64| line_spans.map(|map| map.get(&num).map_or(&[][..], Vec::as_slice));
+        // Where the first NUL byte is, once one has been seen.
+        let mut binary = if self.binary_detection { find_nul(&mut reader) } else { None };
+        let mut count = 0;
+        let mut ctx = Ctx::with_capacity(self.before);
+        // Lines still to send after the last match.
+        let mut rem_after = 0;
+        let mut last_sent: Option<u64> = None;
+        let mut buf = Vec::new();
+        let mut line_num = 0;
+        let mut offset = 0;
+        loop {
+            // After the last match -m allows, lines are only read for the
+            // context that follows it. Nothing more is read once that's done,
+            // as the reader may have to wait for it.
+            let done = self.max_count == Some(count);
+            if done && rem_after == 0 {
+                break;
+            }
+            buf.clear();
+            let len = reader.read_until(b'\n', &mut buf)? as u64;
+            if len == 0 {
+                break;
+            }
+            line_num += 1;
+            offset += len;
+            let line = Line {
+                text: &buf,
+                num: line_num,
+                offset: offset - len,
+                spans: spans_of(line_num),
+            };
+            if binary.is_none() && self.binary_detection {
+                binary = binary_offset(line.text).map(|i| line.offset + i as u64);
+            }
+            let is_match = !done
+                && match line_spans {
+                    Some(map) => map.contains_key(&line.num) != matcher.invert(),
+                    None => matcher.matches(line.content()),
+                };
+            if is_match {
+                count += 1;
+            }
+            if let Some(binary) = binary {
+                if !is_match {
+                    continue;
+                }
+                sink.binary_match(binary)?;
+                break;
+            }
+            if is_match {
+                let first = ctx.first().unwrap_or(line.num);
+                if let (true, Some(last)) = (self.before + self.after > 0, last_sent) {
+                    if first > last + 1 {
+                        sink.context_break()?;
+                    }
+                }
+                for (num, offset, text) in ctx.drain() {
+                    sink.context(&Line { text: &text, num, offset, spans: spans_of(num) })?;
+                }
+                sink.matched(&line)?;
+                last_sent = Some(line.num);
+                rem_after = self.after;
+            } else if rem_after > 0 {
+                sink.context(&line)?;
+                last_sent = Some(line.num);
+                rem_after -= 1;
+            } else {
+                ctx.push_back(&line);
+            }
+        }
+        stats.matched_lines = count;
+        stats.bytes_searched = offset;
+        stats.binary_offset = binary;
+        sink.finish(&stats)?;
+        Ok(stats)
+    }
+}
+
+/// Where the first NUL byte in `data` is, which is what makes input count
+/// as binary.
+pub fn binary_offset(data: &[u8]) -> Option<usize> {
+    memchr::memchr(0, data)
+}
+
+/// Like `binary_offset`, but only looks in the first `BINARY_CHECK_LEN`
+/// bytes, which is as much as is checked before searching.
+pub fn binary_offset_head(data: &[u8]) -> Option<usize> {
+    binary_offset(&data[..data.len().min(BINARY_CHECK_LEN)])
+}
+
+// Looks for a NUL byte in the start of the input without consuming anything.
+// A reader over a slice hands back all of it at once, so only the first
+// `BINARY_CHECK_LEN` bytes are looked at, whatever the reader.
+fn find_nul<R: BufRead>(reader: &mut R) -> Option<u64> {
+    match reader.fill_buf() {
+        Ok(buf) => binary_offset_head(buf).map(|i| i as u64),
+        Err(_) => None,
+    }
+}
+
+// Lines that may be needed as leading context for a later match, with their
+// line numbers.
+struct Ctx {
+    data: VecDeque<(u64, u64, Vec<u8>)>,
+    capacity: usize,
+}
+
+impl Ctx {
+    pub fn with_capacity(capacity: usize) -> Ctx {
+        Ctx {
+            data: VecDeque::with_capacity(capacity),
+            capacity
+        }
+    }
+
+    pub fn push_back(&mut self, line: &Line) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.data.len() == self.capacity {
+            self.data.pop_front();
+        }
+        self.data.push_back((line.num, line.offset, line.text.to_vec()));
+    }
+
+    // Line number of the oldest line held.
+    pub fn first(&self) -> Option<u64> {
+        self.data.front().map(|(n, _, _)| *n)
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = (u64, u64, Vec<u8>)> + '_ {
+        self.data.drain(..)
+    }
+}
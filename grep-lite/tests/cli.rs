@@ -0,0 +1,503 @@
+use std::fs;
+use std::io::{prelude::*, BufReader};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+fn grep_lite(dir: &Path, args: &[&str]) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_grep-lite"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap();
+    String::from_utf8(out.stdout).unwrap()
+}
+
+fn write(dir: &Path, name: &str, contents: &[u8]) {
+    let path = dir.join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+// A small source tree with something for each kind of file the walk skips.
+fn tree() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path();
+    write(root, ".gitignore", b"target\n");
+    write(root, ".ignore", b"*.log\n");
+    write(root, "src/main.rs", b"fn main() {}\n// needle\n");
+    write(root, "src/util.py", b"needle = 1\n");
+    write(root, "src/.secret.rs", b"needle\n");
+    write(root, "run.log", b"needle\n");
+    write(root, "target/out.rs", b"needle\n");
+    write(root, "data.bin", b"needle\0\x01\x02");
+    dir
+}
+
+#[test]
+fn recursive_search_skips_ignored_hidden_and_binary_files() {
+    let dir = tree();
+    assert_eq!(
+        grep_lite(dir.path(), &["-n", "needle", ".", "--sort", "path"]),
+        "./src/main.rs:2:// needle\n./src/util.py:1:needle = 1\n"
+    );
+}
+
+#[test]
+fn hidden_and_ignored_files_can_be_included() {
+    let dir = tree();
+    assert_eq!(
+        grep_lite(dir.path(), &["needle", "src", "--hidden", "--sort", "path"]),
+        "src/.secret.rs:needle\nsrc/main.rs:// needle\nsrc/util.py:needle = 1\n"
+    );
+    let out = grep_lite(dir.path(), &["needle", ".", "--no-ignore"]);
+    assert!(out.contains("./run.log:needle\n"), "{}", out);
+    assert!(out.contains("./target/out.rs:needle\n"), "{}", out);
+}
+
+#[test]
+fn glob_and_type_filters() {
+    let dir = tree();
+    assert_eq!(
+        grep_lite(dir.path(), &["needle", ".", "-t", "py"]),
+        "./src/util.py:needle = 1\n"
+    );
+    assert_eq!(
+        grep_lite(dir.path(), &["needle", ".", "--glob", "!*.py"]),
+        "./src/main.rs:// needle\n"
+    );
+}
+
+#[test]
+fn file_names_are_only_printed_for_several_files() {
+    let dir = tree();
+    assert_eq!(grep_lite(dir.path(), &["needle", "src/util.py"]), "needle = 1\n");
+    assert_eq!(
+        grep_lite(dir.path(), &["needle", "src/util.py", "run.log", "--sort", "path"]),
+        "src/util.py:needle = 1\nrun.log:needle\n"
+    );
+}
+
+#[test]
+fn parallel_output_is_grouped_by_file_and_sortable() {
+    let dir = tempfile::tempdir().unwrap();
+    for i in 0..50 {
+        let lines: String = (0..20).map(|j| format!("file {} line {}\n", i, j)).collect();
+        write(dir.path(), &format!("d{}/f{:02}.txt", i % 5, i), lines.as_bytes());
+    }
+    let single = grep_lite(dir.path(), &["-n", "line", ".", "-j", "1", "--sort", "path"]);
+    let parallel = grep_lite(dir.path(), &["-n", "line", ".", "-j", "8", "--sort", "path"]);
+    assert_eq!(single.lines().count(), 1000);
+    assert_eq!(single, parallel);
+
+    // Unsorted, files may come in any order but never interleave.
+    let unsorted = grep_lite(dir.path(), &["line", ".", "-j", "8"]);
+    let mut files: Vec<&str> = Vec::new();
+    for line in unsorted.lines() {
+        let file = line.split(':').next().unwrap();
+        if files.last() != Some(&file) {
+            assert!(!files.contains(&file), "{} is split up", file);
+            files.push(file);
+        }
+    }
+    assert_eq!(files.len(), 50);
+}
+
+fn lines(text: &str) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "in.txt", text.as_bytes());
+    dir
+}
+
+#[test]
+fn match_selection_flags() {
+    let dir = lines("Foo bar\nfoobar\nfoo\na.c\nabc\n");
+    let d = dir.path();
+    assert_eq!(grep_lite(d, &["-i", "FOO", "in.txt"]), "Foo bar\nfoobar\nfoo\n");
+    assert_eq!(grep_lite(d, &["-w", "foo", "in.txt"]), "foo\n");
+    assert_eq!(grep_lite(d, &["-iw", "foo", "in.txt"]), "Foo bar\nfoo\n");
+    assert_eq!(grep_lite(d, &["-x", "foo|abc", "in.txt"]), "foo\nabc\n");
+    assert_eq!(grep_lite(d, &["-v", "o", "in.txt"]), "a.c\nabc\n");
+    assert_eq!(grep_lite(d, &["a.c", "in.txt"]), "a.c\nabc\n");
+    assert_eq!(grep_lite(d, &["-F", "a.c", "in.txt"]), "a.c\n");
+    assert_eq!(grep_lite(d, &["-F", "-G", "a.c", "in.txt"]), "a.c\nabc\n");
+}
+
+#[test]
+fn several_patterns() {
+    let dir = lines("one\ntwo\nthree\n-x\n");
+    let d = dir.path();
+    write(d, "patterns", b"thr\none\n");
+    assert_eq!(grep_lite(d, &["-e", "two", "-e", "one", "in.txt"]), "one\ntwo\n");
+    assert_eq!(grep_lite(d, &["-f", "patterns", "in.txt"]), "one\nthree\n");
+    assert_eq!(grep_lite(d, &["-f", "patterns", "-e", "-x", "in.txt"]), "one\nthree\n-x\n");
+    // An empty pattern file matches nothing.
+    write(d, "none", b"");
+    assert_eq!(grep_lite(d, &["-f", "none", "in.txt"]), "");
+    assert_eq!(grep_lite(d, &["-v", "-f", "none", "in.txt"]), "one\ntwo\nthree\n-x\n");
+}
+
+#[test]
+fn inverted_match_with_context() {
+    let dir = lines("a\nx\nb\nc\nx\nx\nd\n");
+    assert_eq!(
+        grep_lite(dir.path(), &["-n", "-v", "-C", "1", "[a-d]", "in.txt"]),
+        "1-a\n2:x\n3-b\n4-c\n5:x\n6:x\n7-d\n"
+    );
+}
+
+fn status(dir: &Path, args: &[&str]) -> i32 {
+    Command::new(env!("CARGO_BIN_EXE_grep-lite"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+        .status
+        .code()
+        .unwrap()
+}
+
+#[test]
+fn summary_modes() {
+    let dir = lines("a\nb\na\n");
+    let d = dir.path();
+    write(d, "other.txt", b"b\n");
+    assert_eq!(grep_lite(d, &["-c", "a", "in.txt"]), "2\n");
+    assert_eq!(grep_lite(d, &["-c", "a", "in.txt", "other.txt"]), "in.txt:2\nother.txt:0\n");
+    assert_eq!(grep_lite(d, &["-l", "a", "in.txt", "other.txt"]), "in.txt\n");
+    assert_eq!(grep_lite(d, &["-L", "a", "in.txt", "other.txt"]), "other.txt\n");
+    assert_eq!(grep_lite(d, &["-q", "a", "in.txt"]), "");
+    assert_eq!(grep_lite(d, &["-m", "1", "-c", "a", "in.txt"]), "1\n");
+}
+
+#[test]
+fn max_count_still_prints_trailing_context() {
+    let dir = lines("a\nb\na\nc\nd\n");
+    assert_eq!(
+        grep_lite(dir.path(), &["-n", "-m", "1", "-C", "2", "a", "in.txt"]),
+        "1:a\n2-b\n3-a\n"
+    );
+}
+
+#[test]
+fn exit_status() {
+    let dir = lines("a\n");
+    let d = dir.path();
+    assert_eq!(status(d, &["a", "in.txt"]), 0);
+    assert_eq!(status(d, &["z", "in.txt"]), 1);
+    assert_eq!(status(d, &["a", "missing.txt"]), 2);
+    assert_eq!(status(d, &["a", "missing.txt", "in.txt"]), 2);
+    assert_eq!(status(d, &["(", "in.txt"]), 2);
+    // A match is all -q needs, even if something else went wrong.
+    assert_eq!(status(d, &["-q", "a", "missing.txt", "in.txt"]), 0);
+    assert_eq!(status(d, &["-q", "z", "missing.txt", "in.txt"]), 2);
+}
+
+#[test]
+fn invalid_utf8_does_not_stop_the_search() {
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "in.txt", b"\xff needle\nneedle\n");
+    assert_eq!(grep_lite(dir.path(), &["-c", "needle", "in.txt"]), "2\n");
+    // Lines are printed as they were, not with the bad byte replaced.
+    let out = Command::new(env!("CARGO_BIN_EXE_grep-lite"))
+        .current_dir(dir.path())
+        .args(["needle", "in.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(out.stdout, b"\xff needle\nneedle\n");
+}
+
+#[test]
+fn binary_files_only_say_that_they_match() {
+    let dir = tempfile::tempdir().unwrap();
+    let d = dir.path();
+    write(d, "data.bin", b"needle\nmore\0\nneedle\n");
+    let out = Command::new(env!("CARGO_BIN_EXE_grep-lite"))
+        .current_dir(d)
+        .args(["needle", "data.bin"])
+        .output()
+        .unwrap();
+    assert_eq!(out.stdout, b"");
+    assert_eq!(
+        String::from_utf8(out.stderr).unwrap(),
+        "grep-lite: data.bin: binary file matches\n"
+    );
+    assert_eq!(out.status.code(), Some(0));
+    assert_eq!(grep_lite(d, &["-c", "needle", "data.bin"]), "2\n");
+    assert_eq!(grep_lite(d, &["-a", "-n", "needle", "data.bin"]), "1:needle\n3:needle\n");
+    // Walking a directory skips binary files, unless -a is given.
+    assert_eq!(grep_lite(d, &["needle", "."]), "");
+    assert_eq!(grep_lite(d, &["-a", "-c", "needle", "."]), "./data.bin:2\n");
+}
+
+#[test]
+fn other_encodings_are_transcoded() {
+    let dir = tempfile::tempdir().unwrap();
+    let d = dir.path();
+    write(d, "latin1.txt", b"caf\xe9\n");
+    write(d, "bom.txt", b"\xff\xfec\0a\0f\0\xe9\0\n\0");
+    write(d, "be.txt", b"\0c\0a\0f\0\xe9\0\n");
+    assert_eq!(grep_lite(d, &["--encoding", "latin1", "caf.", "latin1.txt"]), "café\n");
+    // A byte order mark is enough to recognize UTF-16.
+    assert_eq!(grep_lite(d, &["café", "bom.txt"]), "café\n");
+    assert_eq!(grep_lite(d, &["--encoding", "utf-16be", "café", "be.txt"]), "café\n");
+    assert_eq!(status(d, &["--encoding", "nonsense", "x", "latin1.txt"]), 2);
+}
+
+#[test]
+fn grep_colors_are_followed() {
+    let dir = lines("a needle here\n");
+    let out = Command::new(env!("CARGO_BIN_EXE_grep-lite"))
+        .current_dir(dir.path())
+        .env("GREP_COLORS", "ms=4:sl=1:ne")
+        .args(["--color=always", "needle", "in.txt"])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(out.stdout).unwrap(),
+        "\x1b[1ma \x1b[4mneedle\x1b[m\x1b[1m here\x1b[m\n"
+    );
+    // Output that isn't a terminal is left alone unless asked for.
+    assert_eq!(grep_lite(dir.path(), &["needle", "in.txt"]), "a needle here\n");
+    assert_eq!(grep_lite(dir.path(), &["--color=never", "needle", "in.txt"]), "a needle here\n");
+}
+
+#[test]
+fn adjacent_words_are_all_found() {
+    let dir = lines("foo foo,foo foobar\n");
+    assert_eq!(grep_lite(dir.path(), &["-o", "-w", "foo", "in.txt"]), "foo\nfoo\nfoo\n");
+}
+
+fn json_events(text: &str) -> Vec<serde_json::Value> {
+    text.lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+}
+
+#[test]
+fn json_reports_lines_offsets_and_spans() {
+    let dir = lines("one\ntwo foo\nthree\nfoo foo\n");
+    let out = grep_lite(dir.path(), &["--json", "-A1", "foo", "in.txt"]);
+    let events = json_events(&out);
+    let kinds: Vec<_> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["begin", "match", "context", "match", "end"]);
+    assert_eq!(events[0]["data"]["path"]["text"], "in.txt");
+
+    let first = &events[1]["data"];
+    assert_eq!(first["lines"]["text"], "two foo\n");
+    assert_eq!(first["line_number"], 2);
+    assert_eq!(first["absolute_offset"], 4);
+    assert_eq!(first["submatches"][0]["match"]["text"], "foo");
+    assert_eq!(first["submatches"][0]["start"], 4);
+    assert_eq!(first["submatches"][0]["end"], 7);
+    assert_eq!(events[2]["data"]["submatches"].as_array().unwrap().len(), 0);
+    assert_eq!(events[3]["data"]["absolute_offset"], 18);
+    assert_eq!(events[3]["data"]["submatches"].as_array().unwrap().len(), 2);
+
+    let stats = &events[4]["data"]["stats"];
+    assert_eq!(stats["matched_lines"], 2);
+    assert_eq!(stats["matches"], 3);
+    assert_eq!(stats["bytes_searched"], 26);
+    // Everything before the "end" event itself.
+    let printed = out.trim_end().rfind('\n').unwrap() + 1;
+    assert_eq!(stats["bytes_printed"], printed as u64);
+}
+
+#[test]
+fn json_skips_files_without_matches() {
+    let dir = lines("foo\n");
+    write(dir.path(), "other.txt", b"bar\n");
+    let out = grep_lite(dir.path(), &["--json", "--sort", "path", "foo", "in.txt", "other.txt"]);
+    let paths: Vec<_> = json_events(&out)
+        .iter()
+        .map(|e| e["data"]["path"]["text"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(paths, ["in.txt"; 3]);
+    assert_eq!(status(dir.path(), &["--json", "-c", "foo", "in.txt"]), 2);
+}
+
+#[test]
+fn replace_prints_lines_with_groups_substituted() {
+    let dir = lines("hello world\nfoo bar\nfoobar\n");
+    let d = dir.path();
+    assert_eq!(
+        grep_lite(d, &["--replace", "[$1]", "(o+)", "in.txt"]),
+        "hell[o] w[o]rld\nf[oo] bar\nf[oo]bar\n"
+    );
+    assert_eq!(
+        grep_lite(d, &["--replace", "${word}$$", r"(?P<word>\w+) bar", "in.txt"]),
+        "foo$\n"
+    );
+    // Groups are numbered as written, whatever -w wraps around them.
+    assert_eq!(grep_lite(d, &["-w", "--replace", "$1/$0", "f(o+)", "in.txt"]), "oo/foo bar\n");
+    assert_eq!(grep_lite(d, &["-o", "--replace", "<$0>", "o+", "in.txt"]), "<o>\n<o>\n<oo>\n<oo>\n");
+    assert_eq!(grep_lite(d, &["-n", "-A1", "--replace", "", "world", "in.txt"]), "1:hello \n2-foo bar\n");
+}
+
+#[test]
+fn in_place_rewrites_files_and_keeps_backups() {
+    let dir = lines("hello world\nfoo bar\n");
+    let d = dir.path();
+    write(d, "other.txt", b"left as is\n");
+    assert_eq!(grep_lite(d, &["--in-place=.orig", "--replace", "0", "o", "in.txt", "other.txt"]), "");
+    assert_eq!(fs::read_to_string(d.join("in.txt")).unwrap(), "hell0 w0rld\nf00 bar\n");
+    assert_eq!(fs::read_to_string(d.join("in.txt.orig")).unwrap(), "hello world\nfoo bar\n");
+    // Files without a match are left alone entirely.
+    assert!(!d.join("other.txt.orig").exists());
+
+    assert_eq!(grep_lite(d, &["--in-place", "-m", "1", "--replace", "o", "0", "in.txt"]), "");
+    assert_eq!(fs::read_to_string(d.join("in.txt")).unwrap(), "hello world\nf00 bar\n");
+    assert_eq!(status(d, &["--in-place", "x", "in.txt"]), 2);
+    assert_eq!(status(d, &["--in-place", "--replace", "y", "x", "-"]), 2);
+
+    // A backup that can't be made leaves the file, and nothing else, behind.
+    fs::create_dir(d.join("in.txt.bak")).unwrap();
+    assert_eq!(status(d, &["--in-place=.bak", "--replace", "0", "o", "in.txt"]), 2);
+    assert_eq!(fs::read_to_string(d.join("in.txt")).unwrap(), "hello world\nf00 bar\n");
+    assert!(!d.join("in.txt.grep-lite-tmp").exists());
+}
+
+#[test]
+fn multiline_matches_report_every_line_they_cover() {
+    let dir = lines("a\nfn foo(\n    x: u8,\n) -> u8 {\nb\nfn bar() {\n");
+    let d = dir.path();
+    assert_eq!(
+        grep_lite(d, &["-n", "-U", r"fn \w+\([^)]*\)", "in.txt"]),
+        "2:fn foo(\n3:    x: u8,\n4:) -> u8 {\n6:fn bar() {\n"
+    );
+    assert_eq!(
+        grep_lite(d, &["-n", "-U", "-C", "1", r"u8,\n\)", "in.txt"]),
+        "2-fn foo(\n3:    x: u8,\n4:) -> u8 {\n5-b\n"
+    );
+    // Without -U, a pattern can't see past the end of the line.
+    assert_eq!(grep_lite(d, &["-c", r"u8,\n\)", "in.txt"]), "0\n");
+    assert_eq!(grep_lite(d, &["-c", "-U", "-v", r"\(\n", "in.txt"]), "5\n");
+    // . only crosses lines with --multiline-dotall.
+    assert_eq!(grep_lite(d, &["-U", "foo.*x", "in.txt"]), "");
+    assert_eq!(
+        grep_lite(d, &["-n", "-U", "--multiline-dotall", "foo.*x", "in.txt"]),
+        "2:fn foo(\n3:    x: u8,\n"
+    );
+    assert_eq!(grep_lite(d, &["-o", "-U", r"\(\n +x", "in.txt"]), "(\n    x\n");
+}
+
+fn compressed(name: &str, data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    match name.rsplit('.').next().unwrap() {
+        "gz" => {
+            let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            enc.write_all(data).unwrap();
+            enc.finish().unwrap()
+        }
+        "zst" => zstd::encode_all(data, 0).unwrap(),
+        "xz" => {
+            let mut enc = xz2::write::XzEncoder::new(Vec::new(), 6);
+            enc.write_all(data).unwrap();
+            enc.finish().unwrap()
+        }
+        "bz2" => {
+            let mut enc = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+            enc.write_all(data).unwrap();
+            enc.finish().unwrap()
+        }
+        other => panic!("no compressor for {}", other),
+    }
+}
+
+#[test]
+fn search_zip_looks_inside_compressed_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let d = dir.path();
+    for name in ["logs/app.log.gz", "logs/app.log.zst", "logs/app.log.xz", "logs/app.log.bz2"] {
+        write(d, name, &compressed(name, b"start\nneedle\n"));
+    }
+    // Found by its first bytes, without a telling extension.
+    write(d, "logs/rotated.1", &compressed("x.gz", b"needle\n"));
+    assert_eq!(
+        grep_lite(d, &["-z", "-n", "--sort", "path", "needle", "logs"]),
+        "logs/app.log.bz2:2:needle\nlogs/app.log.gz:2:needle\nlogs/app.log.xz:2:needle\n\
+         logs/app.log.zst:2:needle\nlogs/rotated.1:1:needle\n"
+    );
+    assert_eq!(grep_lite(d, &["-z", "needle", "logs/app.log.xz"]), "needle\n");
+    // Without -z they're binary files, which a walk skips.
+    assert_eq!(grep_lite(d, &["needle", "logs"]), "");
+    // Several gzip streams one after another are all read.
+    let mut both = compressed("x.gz", b"one\n");
+    both.extend(compressed("x.gz", b"two\n"));
+    write(d, "both.gz", &both);
+    assert_eq!(grep_lite(d, &["-z", "-c", "o", "both.gz"]), "2\n");
+    // Text that happens to start like bzip2 is still text.
+    write(d, "b.txt", b"BZhello needle\n");
+    assert_eq!(grep_lite(d, &["-z", "needle", "b.txt"]), "BZhello needle\n");
+    write(d, "empty", &compressed("x.bz2", b""));
+    assert_eq!(grep_lite(d, &["-z", "-c", "needle", "empty"]), "0\n");
+}
+
+#[test]
+fn mapped_files_give_the_same_results() {
+    let dir = tempfile::tempdir().unwrap();
+    let d = dir.path();
+    let mut text = String::new();
+    for i in 0..2_000 {
+        text.push_str(&format!("line {} {}\r\n", i, if i % 7 == 0 { "needle needle" } else { "hay" }));
+    }
+    text.push_str("last needle");
+    write(d, "in.txt", text.as_bytes());
+    for args in [
+        &["-n", "needle"][..],
+        &["-c", "needle"],
+        &["-o", "-n", "-e", "needle", "-e", "hay"],
+        &["-m", "3", "-n", "-F", "ne.dle"],
+        &["-n", "-C", "1", "needle"],
+        &["-v", "-c", "needle"],
+        &["-l", "needle"],
+        &["--color", "always", "needle"],
+    ] {
+        let with = |flag: &'static str| {
+            let mut args = args.to_vec();
+            args.extend([flag, "in.txt"]);
+            grep_lite(d, &args)
+        };
+        assert_eq!(with("--mmap"), with("--no-mmap"), "{:?}", args);
+    }
+    assert_eq!(grep_lite(d, &["--mmap", "-c", "needle", "in.txt"]), "287\n");
+}
+
+#[test]
+fn follow_reads_on_through_truncation_and_rotation() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("app.log");
+    fs::write(&log, "old match\nnothing\n").unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_grep-lite"))
+        .current_dir(dir.path())
+        .args(["--follow", "-n", "-B1", "-m", "4", "match", "app.log"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut out = BufReader::new(child.stdout.take().unwrap());
+    // Each line is printed as soon as it's found, so these don't wait for
+    // anything that comes later.
+    let mut expect = |lines: &[&str]| {
+        for expected in lines {
+            let mut line = String::new();
+            out.read_line(&mut line).unwrap();
+            assert_eq!(line, format!("{}\n", expected));
+        }
+    };
+    expect(&["1:old match"]);
+    let mut file = fs::OpenOptions::new().append(true).open(&log).unwrap();
+    file.write_all(b"x\nnew match\n").unwrap();
+    expect(&["--", "3-x", "4:new match"]);
+    fs::write(&log, "match after truncation\n").unwrap();
+    expect(&["5:match after truncation"]);
+    fs::rename(&log, dir.path().join("app.log.1")).unwrap();
+    fs::write(&log, "rotated\nrotated match\n").unwrap();
+    expect(&["6-rotated", "7:rotated match"]);
+    // That was the fourth match, so it stops there.
+    assert!(child.wait().unwrap().success());
+    let mut err = String::new();
+    child.stderr.unwrap().read_to_string(&mut err).unwrap();
+    assert_eq!(
+        err,
+        "grep-lite: app.log: file truncated\n\
+         grep-lite: app.log: file replaced, following the new one\n"
+    );
+}
@@ -0,0 +1,75 @@
+// Context and color output is checked against what GNU grep prints for the
+// same arguments. The expected output lives in tests/fixtures/expected, and can be
+// regenerated by running the ignored `matches_gnu_grep` test with
+// GREP_LITE_BLESS=1 on a machine with GNU grep installed.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const CASES: &[(&str, &[&str])] = &[
+    ("context", &["-n", "-C", "1", "oo", "test.txt"]),
+    ("after", &["-n", "-A", "2", "Yes", "test.txt"]),
+    ("before", &["-n", "-B", "3", "Yes", "test.txt"]),
+    ("before_and_after", &["-A", "1", "-B", "2", "-n", "No", "test.txt"]),
+    ("overlapping", &["-n", "-C", "1", "x", "tests/fixtures/context.txt"]),
+    ("adjacent", &["-n", "-B", "1", "x", "tests/fixtures/context.txt"]),
+    ("no_line_numbers", &["-A", "1", "x", "tests/fixtures/context.txt"]),
+    ("after_overrides_context", &["-C", "1", "-A", "0", "x", "tests/fixtures/context.txt"]),
+    ("custom_separator", &["-n", "-C", "2", "--group-separator=**", "oo", "test.txt"]),
+    ("no_separator", &["--no-group-separator", "-n", "-A", "1", "face", "test.txt"]),
+    ("inverted", &["-n", "-C", "1", "-v", "o", "test.txt"]),
+    ("max_count", &["-n", "-C", "1", "-m", "2", "x", "tests/fixtures/context.txt"]),
+    ("two_files", &["-n", "-C", "1", "x", "tests/fixtures/context.txt", "test.txt"]),
+    ("only_matching", &["-o", "-n", "-i", "-e", "yes", "-e", "oo", "test.txt"]),
+    ("only_matching_context", &["-o", "-C", "1", "-n", "oo", "test.txt"]),
+    ("only_matching_words", &["-o", "-w", "oo", "test.txt"]),
+    ("color", &["--color=always", "-n", "-C", "1", "oo", "test.txt", "tests/fixtures/context.txt"]),
+    ("color_inverted", &["--color=always", "-v", "-C", "1", "x", "tests/fixtures/context.txt"]),
+    ("color_count", &["--color=always", "-c", "oo", "test.txt", "tests/fixtures/context.txt"]),
+];
+
+fn root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn run(program: &Path, args: &[&str]) -> String {
+    let out = Command::new(program)
+        .current_dir(root())
+        .env_remove("GREP_COLORS")
+        .args(args)
+        .output()
+        .unwrap();
+    String::from_utf8(out.stdout).unwrap()
+}
+
+fn expected_path(name: &str) -> PathBuf {
+    root().join("tests/fixtures/expected").join(format!("{}.txt", name))
+}
+
+#[test]
+fn context_output() {
+    let grep_lite = Path::new(env!("CARGO_BIN_EXE_grep-lite"));
+    for (name, args) in CASES {
+        let mut sorted = args.to_vec();
+        sorted.extend(["--sort", "path"]);
+        let expected = fs::read_to_string(expected_path(name)).unwrap();
+        assert_eq!(run(grep_lite, &sorted), expected, "case {}: {:?}", name, args);
+    }
+}
+
+#[test]
+#[ignore = "needs GNU grep"]
+fn matches_gnu_grep() {
+    let bless = env::var_os("GREP_LITE_BLESS").is_some();
+    for (name, args) in CASES {
+        let gnu = run(Path::new("grep"), args);
+        if bless {
+            fs::write(expected_path(name), &gnu).unwrap();
+        } else {
+            let expected = fs::read_to_string(expected_path(name)).unwrap();
+            assert_eq!(gnu, expected, "case {}: {:?}", name, args);
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use grep_lite::{Colors, MatchOpts, Matcher, PrintOpts, Replacement, Searcher, StandardSink};
+
+const TEXT: &[u8] = b"one\ntwo\nthree\nfour\nfive\n";
+
+fn print(searcher: &Searcher, matcher: &Matcher, opts: &PrintOpts) -> String {
+    let mut out = Vec::new();
+    let mut sink = StandardSink::new(&mut out, "f.txt", matcher, opts);
+    searcher.search_reader(matcher, TEXT, &mut sink).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn lines_are_printed_with_name_number_and_separators() {
+    let matcher = Matcher::new(&["o".to_string()], &MatchOpts::default()).unwrap();
+    let opts = PrintOpts {
+        with_filename: true,
+        line_number: true,
+        group_separator: Some("--".to_string()),
+        ..PrintOpts::default()
+    };
+    let searcher = Searcher::new().after_context(1);
+    assert_eq!(
+        print(&searcher, &matcher, &opts),
+        "f.txt:1:one\nf.txt:2:two\nf.txt-3-three\nf.txt:4:four\nf.txt-5-five\n"
+    );
+}
+
+#[test]
+fn only_matching_prints_replacements_in_color() {
+    let matcher = Matcher::new(&["t(.)".to_string()], &MatchOpts::default()).unwrap();
+    let opts = PrintOpts {
+        only_matching: true,
+        colors: Colors::parse(""),
+        replace: Some(Replacement::new("<$1>", &matcher)),
+        ..PrintOpts::default()
+    };
+    assert_eq!(
+        print(&Searcher::new(), &matcher, &opts),
+        "\x1b[01;31m\x1b[K<w>\x1b[m\x1b[K\n\x1b[01;31m\x1b[K<h>\x1b[m\x1b[K\n"
+    );
+}
@@ -0,0 +1,172 @@
+use std::io;
+
+use grep_lite::{Line, MatchOpts, Matcher, SearchStats, Searcher, Sink};
+
+// Writes each event down the way grep would print it with -n.
+#[derive(Default)]
+struct Events {
+    lines: Vec<String>,
+    stats: Option<SearchStats>,
+}
+
+impl Events {
+    fn push(&mut self, line: &Line, delim: char) {
+        let text = String::from_utf8_lossy(line.content());
+        self.lines.push(format!("{}{}{}", line.num, delim, text));
+    }
+}
+
+impl Sink for Events {
+    fn matched(&mut self, line: &Line) -> io::Result<()> {
+        self.push(line, ':');
+        Ok(())
+    }
+
+    fn context(&mut self, line: &Line) -> io::Result<()> {
+        self.push(line, '-');
+        Ok(())
+    }
+
+    fn context_break(&mut self) -> io::Result<()> {
+        self.lines.push("--".to_string());
+        Ok(())
+    }
+
+    fn binary_match(&mut self, offset: u64) -> io::Result<()> {
+        self.lines.push(format!("binary at {}", offset));
+        Ok(())
+    }
+
+    fn finish(&mut self, stats: &SearchStats) -> io::Result<()> {
+        self.stats = Some(*stats);
+        Ok(())
+    }
+}
+
+fn matcher(pattern: &str, opts: MatchOpts) -> Matcher {
+    Matcher::new(&[pattern.to_string()], &opts).unwrap()
+}
+
+fn search(searcher: &Searcher, matcher: &Matcher, input: &[u8]) -> Events {
+    let mut events = Events::default();
+    let stats = searcher.search_reader(matcher, input, &mut events).unwrap();
+    assert_eq!(events.stats, Some(stats));
+    events
+}
+
+const TEXT: &[u8] = b"one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\n";
+
+#[test]
+fn lines_are_numbered_and_context_is_grouped() {
+    let searcher = Searcher::new().before_context(1).after_context(1);
+    let events = search(&searcher, &matcher("^t", MatchOpts::default()), TEXT);
+    assert_eq!(events.lines, ["1-one", "2:two", "3:three", "4-four"]);
+
+    let events = search(&searcher, &matcher("o", MatchOpts::default()), TEXT);
+    assert_eq!(events.lines, ["1:one", "2:two", "3-three", "4:four", "5-five"]);
+
+    let events = search(&searcher, &matcher("^(two|eight)$", MatchOpts::default()), TEXT);
+    assert_eq!(events.lines, ["1-one", "2:two", "3-three", "--", "7-seven", "8:eight", "9-nine"]);
+    let stats = events.stats.unwrap();
+    assert_eq!((stats.matched_lines, stats.bytes_searched), (2, TEXT.len() as u64));
+}
+
+#[test]
+fn inverted_matches_are_the_selected_lines() {
+    let opts = MatchOpts { invert: true, ..MatchOpts::default() };
+    let events = search(&Searcher::new(), &matcher("e", opts), TEXT);
+    assert_eq!(events.lines, ["2:two", "4:four", "6:six"]);
+}
+
+#[test]
+fn max_count_stops_after_the_trailing_context() {
+    let searcher = Searcher::new().after_context(1).max_count(Some(2));
+    let events = search(&searcher, &matcher("e", MatchOpts::default()), TEXT);
+    assert_eq!(events.lines, ["1:one", "2-two", "3:three", "4-four"]);
+    let stats = events.stats.unwrap();
+    assert_eq!((stats.matched_lines, stats.bytes_searched), (2, 19));
+}
+
+#[test]
+fn binary_input_gets_a_single_event() {
+    let input = b"match\nbefore\n\0\nafter match\nmatch\n";
+    let m = matcher("match", MatchOpts::default());
+    let events = search(&Searcher::new().binary_detection(true), &m, input);
+    assert_eq!(events.lines, ["binary at 13"]);
+    assert_eq!(events.stats.unwrap().binary_offset, Some(13));
+
+    // Without detection, NULs are just bytes.
+    let events = search(&Searcher::new(), &m, input);
+    assert_eq!(events.lines, ["1:match", "4:after match", "5:match"]);
+    assert_eq!(events.stats.unwrap().binary_offset, None);
+}
+
+#[test]
+fn a_nul_after_the_first_buffer_stops_printing_from_there() {
+    let input = b"match\nmore\0match\nmatch\n";
+    // A reader whose first buffer ends before the NUL.
+    let reader = io::BufReader::with_capacity(4, &input[..]);
+    let mut events = Events::default();
+    Searcher::new()
+        .binary_detection(true)
+        .search_reader(&matcher("match", MatchOpts::default()), reader, &mut events)
+        .unwrap();
+    assert_eq!(events.lines, ["1:match", "binary at 10"]);
+}
+
+#[test]
+fn multiline_matches_cover_each_of_their_lines() {
+    let opts = MatchOpts { multiline: true, ..MatchOpts::default() };
+    let m = matcher(r"two\nth", opts);
+    let mut events = Vec::new();
+    struct Spans<'a>(&'a Matcher, &'a mut Vec<(u64, Vec<(usize, usize)>)>);
+    impl Sink for Spans<'_> {
+        fn matched(&mut self, line: &Line) -> io::Result<()> {
+            self.1.push((line.num, line.find_spans(self.0)));
+            Ok(())
+        }
+    }
+    Searcher::new().search_reader(&m, TEXT, &mut Spans(&m, &mut events)).unwrap();
+    assert_eq!(events, [(2, vec![(0, 3)]), (3, vec![(0, 2)])]);
+}
+
+#[test]
+fn slices_give_the_same_events_as_readers() {
+    let plain = MatchOpts::default();
+    let searchers = [
+        Searcher::new(),
+        Searcher::new().max_count(Some(2)),
+        Searcher::new().before_context(2),
+        Searcher::new().binary_detection(true),
+    ];
+    for m in [matcher("e", plain.clone()), matcher("i.", plain.clone()), matcher("zzz", plain)] {
+        for searcher in &searchers {
+            for input in [TEXT, b"five\nfi\0ve\n", b"no newline at the end"] {
+                let mut from_slice = Events::default();
+                searcher.search_slice(&m, input, &mut from_slice).unwrap();
+                let from_reader = search(searcher, &m, input);
+                assert_eq!(from_slice.lines, from_reader.lines);
+                let (slice, reader) = (from_slice.stats.unwrap(), from_reader.stats.unwrap());
+                assert_eq!(slice.matched_lines, reader.matched_lines);
+                assert_eq!(slice.binary_offset, reader.binary_offset);
+            }
+        }
+    }
+}
+
+#[test]
+fn a_nul_past_the_start_of_a_slice_stops_printing_from_there() {
+    let mut input = b"match\n".to_vec();
+    input.extend(b"filler\n".repeat(2000));
+    let nul = input.len() as u64;
+    input.extend(b"\0 match\nmatch\n");
+    let m = matcher("match", MatchOpts::default());
+    let searcher = Searcher::new().binary_detection(true);
+    let expected = ["1:match".to_string(), format!("binary at {}", nul)];
+
+    let mut from_slice = Events::default();
+    searcher.search_slice(&m, &input, &mut from_slice).unwrap();
+    assert_eq!(from_slice.lines, expected);
+    assert_eq!(from_slice.stats.unwrap().binary_offset, Some(nul));
+    assert_eq!(search(&searcher, &m, &input).lines, expected);
+}